@@ -0,0 +1,352 @@
+use std::sync::mpsc::{ self, Receiver, RecvTimeoutError, Sender, TryRecvError };
+use std::thread::{ self, JoinHandle };
+use std::time::Duration;
+use log::warn;
+use super::{ Error, Message, OwnedEvent, Result, Zyre };
+
+const POLL_INTERVAL_MS: u64 = 50;
+
+enum Command {
+  Join(String, Sender<Result<()>>),
+  Leave(String, Sender<Result<()>>),
+  Shout(String, Message, Sender<Result<()>>),
+  Whisper(String, Message, Sender<Result<()>>),
+  Subscribe(Sender<OwnedEvent>),
+  Pause(Sender<Result<()>>),
+  Resume(Sender<Result<()>>),
+  Stop,
+}
+
+struct State {
+  subscribers: Vec<Sender<OwnedEvent>>,
+  paused: bool,
+}
+
+/// Owns a Zyre node on a dedicated thread. Interact with it through any
+/// number of `ZyreHandle`s; the node is stopped when the actor drops.
+pub struct ZyreActor {
+  handle: ZyreHandle,
+  thread: Option<JoinHandle<()>>,
+}
+
+/// How to spawn a `ZyreActor`'s thread.
+#[derive(Clone, Debug, Default)]
+pub struct ThreadOptions {
+  name: Option<String>,
+  stack_size: Option<usize>,
+}
+
+impl ThreadOptions {
+  pub fn new() -> ThreadOptions {
+    ThreadOptions::default()
+  }
+
+  /// Shown by debuggers and profilers, and in panic messages.
+  pub fn name<T>(&mut self, name:T) -> &mut ThreadOptions where T:AsRef<str> {
+    self.name = Some(name.as_ref().to_string());
+    self
+  }
+
+  pub fn stack_size(&mut self, bytes:usize) -> &mut ThreadOptions {
+    self.stack_size = Some(bytes);
+    self
+  }
+
+  fn builder(&self) -> thread::Builder {
+    let mut builder = thread::Builder::new();
+
+    if let Some(ref name) = self.name {
+      builder = builder.name(name.clone());
+    }
+    if let Some(bytes) = self.stack_size {
+      builder = builder.stack_size(bytes);
+    }
+
+    builder
+  }
+}
+
+impl ZyreActor {
+  /// Runs `build` on the actor thread, starting the node if `build` didn't.
+  pub fn spawn<F>(build:F) -> Result<ZyreActor>
+  where F:FnOnce() -> Result<Zyre> + Send + 'static {
+    ZyreActor::spawn_with(&ThreadOptions::default(), build)
+  }
+
+  pub fn spawn_with<F>(options:&ThreadOptions, build:F) -> Result<ZyreActor>
+  where F:FnOnce() -> Result<Zyre> + Send + 'static {
+    let (commands, inbox) = mpsc::channel();
+    let (ready, started) = mpsc::channel();
+
+    let spawned = options.builder().spawn(move || {
+      let built = build().and_then(|mut zyre| {
+        if !zyre.is_started() {
+          zyre.start()?;
+        }
+        Ok(zyre)
+      });
+
+      match built {
+        Ok(mut zyre) => {
+          let _ = ready.send(Ok(()));
+          run(&mut zyre, &inbox);
+        },
+        Err(err) => {
+          let _ = ready.send(Err(err));
+        },
+      }
+    });
+    let thread = spawned.map_err(|_| Error::SpawnFailed)?;
+
+    match started.recv() {
+      Ok(Ok(())) => Ok(ZyreActor {
+        handle: ZyreHandle { commands },
+        thread: Some(thread),
+      }),
+      Ok(Err(err)) => {
+        let _ = thread.join();
+        Err(err)
+      },
+      Err(_) => Err(Error::ActorStopped),
+    }
+  }
+
+  pub fn handle(&self) -> ZyreHandle {
+    self.handle.clone()
+  }
+}
+
+impl Drop for ZyreActor {
+  fn drop(&mut self) {
+    let _ = self.handle.commands.send(Command::Stop);
+
+    if let Some(thread) = self.thread.take() {
+      let _ = thread.join();
+    }
+  }
+}
+
+fn run(zyre:&mut Zyre, inbox:&Receiver<Command>) {
+  let poll_interval = Duration::from_millis(POLL_INTERVAL_MS);
+  let mut state = State {
+    subscribers: Vec::new(),
+    paused: false,
+  };
+
+  loop {
+    loop {
+      match inbox.try_recv() {
+        Ok(Command::Stop) | Err(TryRecvError::Disconnected) => return,
+        Ok(command) => execute(zyre, command, &mut state),
+        Err(TryRecvError::Empty) => break,
+      }
+    }
+
+    if state.paused {
+      match inbox.recv_timeout(poll_interval) {
+        Ok(Command::Stop) | Err(RecvTimeoutError::Disconnected) => return,
+        Ok(command) => execute(zyre, command, &mut state),
+        Err(RecvTimeoutError::Timeout) => {},
+      }
+      continue;
+    }
+
+    match zyre.read_event_timeout(poll_interval) {
+      Ok(Some(event)) => match event.snapshot() {
+        Ok(owned) => state.subscribers.retain(|subscriber| subscriber.send(owned.clone()).is_ok()),
+        Err(err) => warn!("zyre actor dropped an event it couldn't copy: {}", err),
+      },
+      Ok(None) => {},
+      // The node can't read again; handles see `ActorStopped` from now on.
+      Err(err @ Error::ReadInterrupted) | Err(err @ Error::NotStarted) => {
+        warn!("zyre actor stopping: {}", err);
+        return;
+      },
+      // One bad event, such as a peer name that isn't UTF-8 under a strict
+      // policy, shouldn't take the actor down.
+      Err(err) => warn!("zyre actor skipped an event: {}", err),
+    }
+  }
+}
+
+fn execute(zyre:&mut Zyre, command:Command, state:&mut State) {
+  match command {
+    Command::Join(group, reply) => {
+      let _ = reply.send(zyre.join(group));
+    },
+    Command::Leave(group, reply) => {
+      let _ = reply.send(zyre.leave(group));
+    },
+    Command::Shout(group, msg, reply) => {
+      let _ = reply.send(zyre.shout(group, msg));
+    },
+    Command::Whisper(peer, msg, reply) => {
+      let _ = reply.send(zyre.whisper(peer, msg));
+    },
+    Command::Subscribe(subscriber) => state.subscribers.push(subscriber),
+    Command::Pause(reply) => {
+      state.paused = true;
+      let _ = reply.send(Ok(()));
+    },
+    Command::Resume(reply) => {
+      state.paused = false;
+      let _ = reply.send(Ok(()));
+    },
+    Command::Stop => {},
+  }
+}
+
+/// A cloneable, `Send + Sync` handle to a `ZyreActor`. Every method blocks
+/// until the actor thread has carried out the command.
+#[derive(Clone)]
+pub struct ZyreHandle {
+  commands: Sender<Command>,
+}
+
+impl ZyreHandle {
+  pub fn join<T>(&self, group:T) -> Result<()> where T:AsRef<str> {
+    let group = group.as_ref().to_string();
+    self.request(|reply| Command::Join(group, reply))
+  }
+
+  pub fn leave<T>(&self, group:T) -> Result<()> where T:AsRef<str> {
+    let group = group.as_ref().to_string();
+    self.request(|reply| Command::Leave(group, reply))
+  }
+
+  pub fn shout<T>(&self, group:T, msg:Message) -> Result<()> where T:AsRef<str> {
+    let group = group.as_ref().to_string();
+    self.request(|reply| Command::Shout(group, msg, reply))
+  }
+
+  pub fn whisper<T>(&self, peer:T, msg:Message) -> Result<()> where T:AsRef<str> {
+    let peer = peer.as_ref().to_string();
+    self.request(|reply| Command::Whisper(peer, msg, reply))
+  }
+
+  /// Returns a channel receiving a copy of every event read from now on.
+  pub fn subscribe_events(&self) -> Result<Receiver<OwnedEvent>> {
+    let (subscriber, events) = mpsc::channel();
+
+    self.commands.send(Command::Subscribe(subscriber)).map_err(|_| Error::ActorStopped)?;
+
+    Ok(events)
+  }
+
+  /// Stops the actor reading events until `resume`, leaving them queued in
+  /// libzyre. Commands are still carried out while paused.
+  ///
+  /// libzyre's pipe to the node has a bounded high-water mark (czmq's pipe
+  /// HWM, 1000 messages by default). Pausing long enough to fill it stalls
+  /// libzyre's own traffic, so keep pauses short.
+  pub fn pause(&self) -> Result<()> {
+    self.request(Command::Pause)
+  }
+
+  pub fn resume(&self) -> Result<()> {
+    self.request(Command::Resume)
+  }
+
+  fn request<F>(&self, command:F) -> Result<()> where F:FnOnce(Sender<Result<()>>) -> Command {
+    let (reply, response) = mpsc::channel();
+
+    self.commands.send(command(reply)).map_err(|_| Error::ActorStopped)?;
+
+    response.recv().unwrap_or(Err(Error::ActorStopped))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{ Message, ZyreBuilder };
+  use super::super::tests::unique_port;
+
+  #[test]
+  fn handle_is_send_sync() {
+    fn assert_send_sync<T:Send + Sync>() {}
+    assert_send_sync::<ZyreHandle>();
+  }
+
+  #[test]
+  fn spawn_drop() {
+    let actor = ZyreActor::spawn(|| Zyre::new(Some("actor"))).unwrap();
+    drop(actor);
+  }
+
+  #[test]
+  fn thread_name() {
+    let (name_tx, name_rx) = mpsc::channel();
+    let _actor = ZyreActor::spawn_with(ThreadOptions::new().name("zyre-test").stack_size(256 * 1024), move || {
+      name_tx.send(thread::current().name().map(String::from)).unwrap();
+      Zyre::new(Some("named"))
+    }).unwrap();
+
+    assert_eq!(name_rx.recv().unwrap(), Some("zyre-test".to_string()));
+  }
+
+  #[test]
+  fn handle_after_stop() {
+    let actor = ZyreActor::spawn(|| Zyre::new(Some("actor"))).unwrap();
+    let handle = actor.handle();
+    drop(actor);
+
+    match handle.join("GLOBAL") {
+      Err(Error::ActorStopped) => {},
+      other => panic!("expected ActorStopped, got {:?}", other),
+    }
+  }
+
+  #[test]
+  fn join_and_subscribe() {
+    let actor = ZyreActor::spawn(|| Zyre::new(Some("actor"))).unwrap();
+    let handle = actor.handle();
+    let events = handle.subscribe_events().unwrap();
+    handle.join("ACTOR").unwrap();
+
+    let mut peer = Zyre::new(Some("actor-peer")).unwrap();
+    peer.start().unwrap();
+    peer.join("ACTOR").unwrap();
+    let peer_uuid = peer.uuid().unwrap().to_string();
+
+    loop {
+      let event = events.recv_timeout(Duration::from_secs(10)).unwrap();
+
+      if event.event_type == "JOIN" && event.peer_uuid == peer_uuid {
+        assert_eq!(event.group, Some("ACTOR".to_string()));
+        break;
+      }
+    }
+  }
+
+  #[test]
+  fn pause_resume() {
+    let port = unique_port();
+    let actor = ZyreActor::spawn(move || {
+      ZyreBuilder::new().name("paused").port(port).build()
+    }).unwrap();
+    let handle = actor.handle();
+    let events = handle.subscribe_events().unwrap();
+    handle.join("PAUSED").unwrap();
+    handle.pause().unwrap();
+
+    let mut peer = ZyreBuilder::new().name("paused-peer").port(port).build().unwrap();
+    peer.start().unwrap();
+    peer.join("PAUSED").unwrap();
+    let peer_uuid = peer.uuid().unwrap().to_string();
+    std::thread::sleep(Duration::from_secs(2));
+    peer.shout("PAUSED", Message::from_frames(vec!["queued"]).unwrap()).unwrap();
+
+    assert!(events.recv_timeout(Duration::from_secs(1)).is_err());
+
+    handle.resume().unwrap();
+    loop {
+      let event = events.recv_timeout(Duration::from_secs(10)).unwrap();
+
+      if event.event_type == "SHOUT" && event.peer_uuid == peer_uuid {
+        assert_eq!(event.frames, vec![b"queued".to_vec()]);
+        break;
+      }
+    }
+  }
+}
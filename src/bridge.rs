@@ -0,0 +1,201 @@
+use std::collections::HashSet;
+use std::time::Duration;
+use super::{ EventType, Result, Zyre, ZyreBuilder };
+
+/// Header every bridge node advertises, holding the bridge's id.
+const BRIDGE_HEADER: &str = "X-BRIDGE";
+
+/// Joins two otherwise isolated Zyre networks by re-shouting each side's
+/// shouts on the other, in the same group.
+///
+/// Both nodes advertise the bridge's id in an `X-BRIDGE` header, and traffic
+/// from a peer advertising the same id is never forwarded, so bridges
+/// sharing an id don't echo each other's traffic. Give every bridge on a
+/// cycle of networks the same id; bridges chained without a cycle need
+/// distinct ids to pass traffic along. Forwarded shouts come from the
+/// bridge node, not the original sender.
+///
+/// UUIDs mean nothing on the other side, so whispers are addressed through
+/// the bridge: a whisper to a bridge node whose first frame is the UUID of a
+/// peer on the other network reaches that peer from the other bridge node,
+/// with that frame replaced by the original sender's UUID so it can reply
+/// the same way. Whispers addressed to anyone else are dropped.
+pub struct Bridge {
+  left: Side,
+  right: Side,
+}
+
+struct Side {
+  zyre: Zyre,
+  id: String,
+  /// Peers on this side advertising this bridge's id.
+  twins: HashSet<String>,
+}
+
+impl Bridge {
+  /// Builds and starts a node from each of `left` and `right`, which should
+  /// `join` the groups to bridge.
+  pub fn new<T>(id:T, left:&ZyreBuilder, right:&ZyreBuilder) -> Result<Bridge> where T:AsRef<str> {
+    let side = |builder:&ZyreBuilder| -> Result<Side> {
+      Ok(Side {
+        zyre: builder.clone().header(BRIDGE_HEADER, id.as_ref()).build_started()?,
+        id: id.as_ref().to_string(),
+        twins: HashSet::new(),
+      })
+    };
+
+    Ok(Bridge {
+      left: side(left)?,
+      right: side(right)?,
+    })
+  }
+
+  pub fn left(&mut self) -> &mut Zyre {
+    &mut self.left.zyre
+  }
+
+  pub fn right(&mut self) -> &mut Zyre {
+    &mut self.right.zyre
+  }
+
+  /// Reads at most one event from each side, waiting up to `timeout` for
+  /// each, and forwards any shouts and addressed whispers. Returns how many
+  /// were forwarded; call it in a loop to keep the bridge running.
+  pub fn pump(&mut self, timeout:Duration) -> Result<usize> {
+    let forwarded = forward(&mut self.left, &mut self.right, timeout)?;
+    Ok(forwarded + forward(&mut self.right, &mut self.left, timeout)?)
+  }
+}
+
+fn forward(from:&mut Side, to:&mut Side, timeout:Duration) -> Result<usize> {
+  let mut event = match from.zyre.read_event_timeout(timeout)? {
+    Some(event) => event,
+    None => return Ok(0),
+  };
+
+  match event.kind()? {
+    EventType::Enter if event.header(BRIDGE_HEADER)? == Some(from.id.as_str()) => {
+      from.twins.insert(event.peer_uuid()?.to_string());
+    },
+    EventType::Exit => {
+      from.twins.remove(event.peer_uuid()?);
+    },
+    EventType::Shout if !from.twins.contains(event.peer_uuid()?) => {
+      let group = event.group()?.to_string();
+      if let Some(msg) = event.take_message() {
+        to.zyre.shout(group, msg)?;
+        return Ok(1);
+      }
+    },
+    EventType::Whisper if !from.twins.contains(event.peer_uuid()?) => {
+      let sender = event.peer_uuid()?.to_string();
+      if let Some(mut msg) = event.take_message() {
+        // Not addressed to a peer on the other side; nothing to forward.
+        let target = match msg.pop() {
+          Ok(ref target) if to.zyre.known.contains_key(target) => target.clone(),
+          _ => return Ok(0),
+        };
+        msg.push(sender)?;
+        to.zyre.whisper(&target, msg)?;
+        return Ok(1);
+      }
+    },
+    _ => {},
+  }
+
+  Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::Instant;
+  use super::super::Message;
+  use super::super::tests::unique_port;
+
+  #[test]
+  fn shout_crosses_bridge() {
+    let (left_port, right_port) = (unique_port(), unique_port());
+    let mut publisher = ZyreBuilder::new().name("publisher").port(left_port).join("NEWS").build_started().unwrap();
+    let mut subscriber = ZyreBuilder::new().name("subscriber").port(right_port).join("NEWS").build_started().unwrap();
+    let mut bridge = Bridge::new(
+      "test-bridge",
+      ZyreBuilder::new().name("bridge-left").port(left_port).join("NEWS"),
+      ZyreBuilder::new().name("bridge-right").port(right_port).join("NEWS"),
+    ).unwrap();
+
+    let bridge_left = bridge.left().uuid().unwrap().to_string();
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let mut joined = false;
+    while !joined || bridge.right().peers_by_group("NEWS").unwrap().is_empty() {
+      assert!(Instant::now() < deadline, "bridge never joined both sides");
+      bridge.pump(Duration::from_millis(10)).unwrap();
+      if let Some(event) = publisher.read_event_timeout(Duration::from_millis(10)).unwrap() {
+        joined |= event.kind().unwrap() == EventType::Join && event.peer_uuid().unwrap() == bridge_left;
+      }
+    }
+
+    publisher.shout("NEWS", Message::from_frames(vec!["extra"]).unwrap()).unwrap();
+
+    let bridge_right = bridge.right().uuid().unwrap().to_string();
+    let deadline = Instant::now() + Duration::from_secs(30);
+    loop {
+      assert!(Instant::now() < deadline, "shout never crossed the bridge");
+      bridge.pump(Duration::from_millis(10)).unwrap();
+      if let Some(mut event) = subscriber.read_event_timeout(Duration::from_millis(10)).unwrap() {
+        if event.kind().unwrap() == EventType::Shout {
+          assert_eq!(event.peer_uuid().unwrap(), bridge_right);
+          assert_eq!(event.group().unwrap(), "NEWS");
+          assert_eq!(event.take_message().unwrap().collect().unwrap(), vec!["extra"]);
+          break;
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn whisper_crosses_bridge() {
+    let (left_port, right_port) = (unique_port(), unique_port());
+    let mut publisher = ZyreBuilder::new().name("publisher").port(left_port).build_started().unwrap();
+    let mut subscriber = ZyreBuilder::new().name("subscriber").port(right_port).build_started().unwrap();
+    let mut bridge = Bridge::new(
+      "test-bridge",
+      ZyreBuilder::new().name("bridge-left").port(left_port),
+      ZyreBuilder::new().name("bridge-right").port(right_port),
+    ).unwrap();
+
+    let bridge_left = bridge.left().uuid().unwrap().to_string();
+    let bridge_right = bridge.right().uuid().unwrap().to_string();
+    let publisher_uuid = publisher.uuid().unwrap().to_string();
+    let (mut publisher_saw, mut subscriber_saw) = (false, false);
+    let deadline = Instant::now() + Duration::from_secs(30);
+    // The bridge only forwards to peers it has seen ENTER.
+    while !(publisher_saw && subscriber_saw && bridge.left.zyre.known.contains_key(&publisher_uuid)) {
+      assert!(Instant::now() < deadline, "bridge never saw both sides");
+      bridge.pump(Duration::from_millis(10)).unwrap();
+      if let Some(event) = publisher.read_event_timeout(Duration::from_millis(10)).unwrap() {
+        publisher_saw |= event.kind().unwrap() == EventType::Enter && event.peer_uuid().unwrap() == bridge_left;
+      }
+      if let Some(event) = subscriber.read_event_timeout(Duration::from_millis(10)).unwrap() {
+        subscriber_saw |= event.kind().unwrap() == EventType::Enter && event.peer_uuid().unwrap() == bridge_right;
+      }
+    }
+
+    // `from_frames` pushes each frame to the front, so the address goes last.
+    subscriber.whisper(&bridge_right, Message::from_frames(vec!["psst", publisher_uuid.as_str()]).unwrap()).unwrap();
+
+    let subscriber_uuid = subscriber.uuid().unwrap().to_string();
+    let deadline = Instant::now() + Duration::from_secs(30);
+    loop {
+      assert!(Instant::now() < deadline, "whisper never crossed the bridge");
+      bridge.pump(Duration::from_millis(10)).unwrap();
+      if let Some(mut event) = publisher.read_event_timeout(Duration::from_millis(10)).unwrap() {
+        if event.kind().unwrap() == EventType::Whisper {
+          assert_eq!(event.peer_uuid().unwrap(), bridge_left);
+          assert_eq!(event.take_message().unwrap().collect().unwrap(), vec!["psst".to_string(), subscriber_uuid]);
+          break;
+        }
+      }
+    }
+  }
+}
@@ -0,0 +1,523 @@
+use std::collections::{ BTreeMap, HashMap };
+use std::path::{ Path, PathBuf };
+use std::thread;
+use std::time::Duration;
+use super::{ Result, Timeouts, Utf8Policy, Zyre };
+
+pub(crate) const DISPLAY_NAME_HEADER: &str = "X-DISPLAY-NAME";
+pub(crate) const APP_HEADER: &str = "X-APP";
+pub(crate) const PROTO_HEADER: &str = "X-PROTO";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AddressFamily {
+  V4,
+  V6,
+}
+
+/// How a node finds its peers, for `Zyre::switch_discovery`. Gossip needs the
+/// node bound to an explicit `endpoint`, since there's no beacon to announce
+/// an ephemeral one.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiscoveryMode {
+  /// UDP beacons on the local network; libzyre's default.
+  Beacon,
+  /// Runs a gossip hub on `hub` for other nodes to connect to.
+  GossipHub { endpoint: String, hub: String },
+  /// Discovers peers through the gossip hub at `hub`.
+  GossipClient { endpoint: String, hub: String },
+}
+
+/// Everything a node was built with, kept so it can be re-applied later.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Config {
+  pub name: Option<String>,
+  pub port: Option<u16>,
+  pub family: Option<AddressFamily>,
+  pub broadcast_address: Option<String>,
+  pub multicast_address: Option<String>,
+  pub max_inbound_frame: Option<usize>,
+  pub max_frames: Option<usize>,
+  pub coalesce: HashMap<String, Duration>,
+  pub headers: BTreeMap<String, String>,
+  pub join: Vec<String>,
+  pub app_id: Option<String>,
+  pub utf8: Utf8Policy,
+  pub timeouts: Timeouts,
+  pub manual_teardown: bool,
+  pub identity_file: Option<PathBuf>,
+  pub contest: Vec<String>,
+  pub start_settle: Option<Duration>,
+  pub interval: Option<Duration>,
+  pub endpoint: Option<String>,
+  pub gossip_bind: Option<String>,
+  pub gossip_connect: Vec<String>,
+}
+
+/// Collects configuration to apply to a node before it starts.
+#[derive(Clone, Debug, Default)]
+pub struct ZyreBuilder {
+  config: Config,
+}
+
+impl ZyreBuilder {
+  pub fn new() -> ZyreBuilder {
+    ZyreBuilder::default()
+  }
+
+  pub fn name<T>(&mut self, name:T) -> &mut ZyreBuilder where T:AsRef<str> {
+    self.config.name = Some(name.as_ref().to_string());
+    self
+  }
+
+  /// Advertises a header to peers, who see it on this node's ENTER event.
+  pub fn header<K, V>(&mut self, name:K, value:V) -> &mut ZyreBuilder where K:AsRef<str>, V:AsRef<str> {
+    self.config.headers.insert(name.as_ref().to_string(), value.as_ref().to_string());
+    self
+  }
+
+  /// Advertises a human-friendly name for peers to show instead of the node
+  /// name, read back with `Event::display_name`.
+  ///
+  /// libzyre always identifies nodes by a random UUID and sends the node name
+  /// as given, so a display name distinct from the node name can only travel
+  /// as a header (`X-DISPLAY-NAME`).
+  pub fn display_name<T>(&mut self, name:T) -> &mut ZyreBuilder where T:AsRef<str> {
+    self.header(DISPLAY_NAME_HEADER, name)
+  }
+
+  /// Tags this node with an application id (the `X-APP` header) and ignores
+  /// peers that don't advertise the same one. Their events are dropped,
+  /// counted in `Stats::foreign`, and they are left out of `peers` and
+  /// `peers_by_group`.
+  ///
+  /// This guards against unrelated Zyre applications sharing a beacon port;
+  /// libzyre itself still connects to those nodes.
+  pub fn app_id<T>(&mut self, id:T) -> &mut ZyreBuilder where T:AsRef<str> {
+    self.config.app_id = Some(id.as_ref().to_string());
+    self.header(APP_HEADER, id)
+  }
+
+  /// Advertises the application protocol version this node speaks as the
+  /// `X-PROTO` header, so peers can cordon off old nodes during a rolling
+  /// upgrade with `Zyre::compatible_peers`.
+  pub fn protocol_version(&mut self, version:u32) -> &mut ZyreBuilder {
+    self.header(PROTO_HEADER, version.to_string())
+  }
+
+  /// UDP port used for beacon discovery.
+  pub fn port(&mut self, port:u16) -> &mut ZyreBuilder {
+    self.config.port = Some(port);
+    self
+  }
+
+  /// How often the node sends discovery beacons. Shorter finds peers sooner
+  /// at the cost of more UDP traffic.
+  pub fn interval(&mut self, interval:Duration) -> &mut ZyreBuilder {
+    self.config.interval = Some(interval);
+    self
+  }
+
+  /// How long a peer may be quiet before an EVASIVE event.
+  pub fn evasive_timeout(&mut self, timeout:Duration) -> &mut ZyreBuilder {
+    self.config.timeouts.evasive = timeout;
+    self
+  }
+
+  /// How long a peer may be quiet before a SILENT event.
+  pub fn silent_timeout(&mut self, timeout:Duration) -> &mut ZyreBuilder {
+    self.config.timeouts.silent = timeout;
+    self
+  }
+
+  /// How long a peer may be quiet before libzyre drops it with EXIT.
+  pub fn expired_timeout(&mut self, timeout:Duration) -> &mut ZyreBuilder {
+    self.config.timeouts.expired = timeout;
+    self
+  }
+
+  /// Binds the node's ZeroMQ socket to `endpoint` instead of an ephemeral
+  /// TCP port. Required when discovering peers by gossip.
+  pub fn endpoint<T>(&mut self, endpoint:T) -> &mut ZyreBuilder where T:AsRef<str> {
+    self.config.endpoint = Some(endpoint.as_ref().to_string());
+    self
+  }
+
+  /// Runs a gossip hub on `endpoint` that other nodes can `gossip_connect`
+  /// to. Gossip replaces UDP beacons, so it works where broadcast doesn't.
+  pub fn gossip_bind<T>(&mut self, endpoint:T) -> &mut ZyreBuilder where T:AsRef<str> {
+    self.config.gossip_bind = Some(endpoint.as_ref().to_string());
+    self
+  }
+
+  /// Discovers peers through the gossip hub at `endpoint`. May be called
+  /// once per hub.
+  pub fn gossip_connect<T>(&mut self, endpoint:T) -> &mut ZyreBuilder where T:AsRef<str> {
+    self.config.gossip_connect.push(endpoint.as_ref().to_string());
+    self
+  }
+
+  /// Forces beaconing over IPv4 or IPv6 rather than czmq's default.
+  ///
+  /// `enable_ipv6` is shorthand for `address_family(AddressFamily::V6)`, so
+  /// whichever of the two is called last wins. czmq keeps this setting
+  /// process-wide: it is applied when the node is built and affects every
+  /// node started afterwards.
+  pub fn address_family(&mut self, family:AddressFamily) -> &mut ZyreBuilder {
+    self.config.family = Some(family);
+    self
+  }
+
+  pub fn enable_ipv6(&mut self) -> &mut ZyreBuilder {
+    self.address_family(AddressFamily::V6)
+  }
+
+  /// Sends this node's IPv4 beacons to the broadcast address `address`.
+  ///
+  /// czmq beacons on the first interface it finds, broadcasting to the
+  /// address derived from that interface's netmask. On hosts with several
+  /// interfaces, or a custom netmask on a secondary one, that may not be the
+  /// network the peers are on; this picks the interface whose broadcast
+  /// address is `address` instead. czmq can't broadcast to an arbitrary
+  /// address, so building fails with `Error::InvalidAddress` if no
+  /// interface broadcasts there. Unlike `multicast_address`, this only
+  /// affects this node.
+  pub fn broadcast_address<T>(&mut self, address:T) -> &mut ZyreBuilder where T:AsRef<str> {
+    self.config.broadcast_address = Some(address.as_ref().to_string());
+    self
+  }
+
+  /// Sends IPv4 beacons to the multicast group `address` rather than
+  /// broadcasting them, for networks that drop broadcast but pass multicast.
+  /// `address` must be an IPv4 multicast group (`224.0.0.0/4`), or building
+  /// fails with `Error::InvalidAddress`.
+  ///
+  /// This setting is process-wide: like `address_family`, it is applied
+  /// when the node is built and affects every node started afterwards.
+  pub fn multicast_address<T>(&mut self, address:T) -> &mut ZyreBuilder where T:AsRef<str> {
+    self.config.multicast_address = Some(address.as_ref().to_string());
+    self
+  }
+
+  /// Drops inbound messages whose total content exceeds `bytes`, counting
+  /// each in `Stats::rejected` instead of returning it.
+  ///
+  /// The check happens after receipt: libzyre has already buffered the
+  /// message by then, so this guards application code, not memory.
+  pub fn max_inbound_frame(&mut self, bytes:usize) -> &mut ZyreBuilder {
+    self.config.max_inbound_frame = Some(bytes);
+    self
+  }
+
+  /// How the node and the events it reads handle invalid UTF-8. Defaults to
+  /// `Utf8Policy::Strict`.
+  pub fn utf8_policy(&mut self, policy:Utf8Policy) -> &mut ZyreBuilder {
+    self.config.utf8 = policy;
+    self
+  }
+
+  /// Drops inbound messages with more than `count` frames, counting each in
+  /// `Stats::rejected`.
+  ///
+  /// Without this a peer could send millions of empty frames, each of which
+  /// `collect` would turn into a `String`. As with `max_inbound_frame`, the
+  /// message has already been received when the check runs.
+  pub fn max_frames(&mut self, count:usize) -> &mut ZyreBuilder {
+    self.config.max_frames = Some(count);
+    self
+  }
+
+  /// Adds a group for `build_started` to join once the node is up. May be
+  /// called once per group.
+  pub fn join<T>(&mut self, group:T) -> &mut ZyreBuilder where T:AsRef<str> {
+    let group = group.as_ref().to_string();
+    if !self.config.join.contains(&group) {
+      self.config.join.push(group);
+    }
+    self
+  }
+
+  /// Makes `build_started` wait `settle` between starting the node and
+  /// joining its groups.
+  ///
+  /// This is a pragmatic workaround rather than a guarantee: libzyre reports
+  /// nothing once its sockets and beacon are fully up, and joins sent in
+  /// the first moments after start are occasionally missed by peers.
+  pub fn start_settle(&mut self, settle:Duration) -> &mut ZyreBuilder {
+    self.config.start_settle = Some(settle);
+    self
+  }
+
+  /// Takes part in leader elections for `group`, which libzyre holds when the
+  /// group's members join. The result arrives as a LEADER event and is
+  /// available from `Zyre::is_leader`. Still needs a `join` to be a member.
+  pub fn contest<T>(&mut self, group:T) -> &mut ZyreBuilder where T:AsRef<str> {
+    let group = group.as_ref().to_string();
+    if !self.config.contest.contains(&group) {
+      self.config.contest.push(group);
+    }
+    self
+  }
+
+  /// Gives the node a lasting identity: the CURVE cert at `path` is loaded,
+  /// or generated and saved there (with the secret key alongside in
+  /// `<path>_secret`) if it doesn't exist, then passed to libzyre, which
+  /// advertises the public key to peers. libzyre still picks a random UUID
+  /// on every start, so use `Zyre::public_key` to recognise a node across
+  /// restarts.
+  pub fn identity_file<P>(&mut self, path:P) -> &mut ZyreBuilder where P:AsRef<Path> {
+    self.config.identity_file = Some(path.as_ref().to_path_buf());
+    self
+  }
+
+  /// Makes dropping the `Zyre` leave the libzyre node alone, for callers who
+  /// stop and destroy it themselves, usually after `Zyre::into_raw` or
+  /// `Zyre::as_raw`.
+  ///
+  /// A node dropped this way without being destroyed by hand leaks, along
+  /// with its sockets and threads, and keeps beaconing until the process
+  /// exits.
+  pub fn manual_teardown(&mut self) -> &mut ZyreBuilder {
+    self.config.manual_teardown = true;
+    self
+  }
+
+  pub fn build(&self) -> Result<Zyre> {
+    Zyre::from_config(self.config.clone())
+  }
+
+  /// Builds and starts the node, then joins every group given to `join`.
+  pub fn build_started(&self) -> Result<Zyre> {
+    let mut zyre = self.build()?;
+    zyre.start()?;
+
+    if let Some(settle) = self.config.start_settle {
+      thread::sleep(settle);
+    }
+
+    for group in &self.config.join {
+      zyre.join(group)?;
+    }
+
+    Ok(zyre)
+  }
+}
+
+impl Zyre {
+  /// Builds a new, unstarted node from this one's configuration after
+  /// `modify` has adjusted it, for fleets of nodes that differ in a setting
+  /// or two. Headers set with `set_header_bytes` carry over; groups joined
+  /// at runtime don't.
+  ///
+  /// The per-node `identity_file`, `endpoint` and `gossip_bind` are cleared
+  /// so variants don't share a keypair or fight over an address; a variant
+  /// of a gossip node must be given its own `endpoint` in `modify`.
+  pub fn spawn_variant<F>(&self, modify:F) -> Result<Zyre> where F:FnOnce(&mut ZyreBuilder) {
+    let mut config = self.config.clone();
+    config.identity_file = None;
+    config.endpoint = None;
+    config.gossip_bind = None;
+
+    let mut builder = ZyreBuilder { config };
+    modify(&mut builder);
+    builder.build()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::Instant;
+  use super::super::{ Error, EventType };
+  use super::super::tests::{ read_until, unique_port };
+
+  #[test]
+  fn build_named() {
+    let zyre = ZyreBuilder::new().name("built").build().unwrap();
+    assert_eq!(zyre.name().unwrap(), "built");
+  }
+
+  #[test]
+  fn spawn_variant() {
+    let port = unique_port();
+    let base = ZyreBuilder::new().name("base").port(port).header("X-FLEET", "sim").interval(Duration::from_millis(250)).build().unwrap();
+
+    let mut first = base.spawn_variant(|builder| { builder.name("first"); }).unwrap();
+    let mut second = base.spawn_variant(|builder| { builder.name("second"); }).unwrap();
+    assert_eq!(first.name().unwrap(), "first");
+    assert_eq!(second.name().unwrap(), "second");
+    assert!(!first.is_started());
+    for variant in &[&first, &second] {
+      assert_eq!(variant.config.port, Some(port));
+      assert_eq!(variant.config.headers, base.config.headers);
+      assert_eq!(variant.effective_interval(), Duration::from_millis(250));
+    }
+    assert_eq!(base.name().unwrap(), "base");
+
+    first.start().unwrap();
+    second.start().unwrap();
+    let enter = read_until(&mut first, |event| event.kind().unwrap() == EventType::Enter);
+    assert_eq!(enter.peer_name().unwrap(), "second");
+    assert_eq!(enter.header("X-FLEET").unwrap(), Some("sim"));
+  }
+
+  #[test]
+  fn spawn_variant_own_endpoint() {
+    let id = unique_port();
+    let hub_endpoint = format!("inproc://variant-hub-{}", id);
+    let mut hub = ZyreBuilder::new().name("hub").endpoint(format!("inproc://hub-{}", id)).gossip_bind(&hub_endpoint).build().unwrap();
+
+    let mut variant = hub.spawn_variant(|builder| {
+      builder.name("variant").endpoint(format!("inproc://variant-{}", id)).gossip_connect(&hub_endpoint);
+    }).unwrap();
+    assert_eq!(variant.config.endpoint, Some(format!("inproc://variant-{}", id)));
+    assert_eq!(variant.config.gossip_bind, None);
+
+    hub.start().unwrap();
+    variant.start().unwrap();
+    let enter = read_until(&mut variant, |event| event.kind().unwrap() == EventType::Enter);
+    assert_eq!(enter.peer_name().unwrap(), "hub");
+  }
+
+  #[test]
+  fn identity_file() {
+    let path = std::env::temp_dir().join(format!("zyre-identity-{}-{}", std::process::id(), unique_port()));
+    std::fs::remove_file(&path).ok();
+
+    let first = ZyreBuilder::new().name("durable").identity_file(&path).build().unwrap();
+    assert!(path.exists());
+    let key = first.public_key().unwrap().to_string();
+    assert_eq!(key.len(), 40);
+    drop(first);
+
+    let second = ZyreBuilder::new().name("durable").identity_file(&path).build().unwrap();
+    assert_eq!(second.public_key(), Some(key.as_str()));
+    assert!(ZyreBuilder::new().name("anonymous").build().unwrap().public_key().is_none());
+
+    std::fs::remove_file(&path).ok();
+    std::fs::remove_file(format!("{}_secret", path.display())).ok();
+  }
+
+  #[test]
+  fn build_started_joins() {
+    let zyre = ZyreBuilder::new().name("joiner").port(unique_port()).join("FIRST").join("SECOND").join("FIRST").build_started().unwrap();
+    assert!(zyre.is_started());
+
+    let mut groups = zyre.own_groups().unwrap();
+    groups.sort();
+    assert_eq!(groups, vec!["FIRST", "SECOND"]);
+  }
+
+  #[test]
+  fn start_settle() {
+    let port = unique_port();
+    let mut observer = ZyreBuilder::new().name("observer").port(port).build_started().unwrap();
+    let settled = ZyreBuilder::new().name("settled").port(port).start_settle(Duration::from_millis(100)).join("SETTLED").build_started().unwrap();
+    assert_eq!(settled.own_groups().unwrap(), vec!["SETTLED"]);
+
+    let uuid = settled.uuid().unwrap().to_string();
+    let join = read_until(&mut observer, |event| event.kind().unwrap() == EventType::Join);
+    assert_eq!(join.peer_uuid().unwrap(), uuid);
+    assert_eq!(join.group().unwrap(), "SETTLED");
+  }
+
+  #[test]
+  fn display_name() {
+    let port = unique_port();
+    let mut first = ZyreBuilder::new().name("first").port(port).build().unwrap();
+    let mut second = ZyreBuilder::new().name("node-b").display_name("Kitchen Display").port(port).build().unwrap();
+    first.start().unwrap();
+    second.start().unwrap();
+
+    let second_uuid = second.uuid().unwrap().to_string();
+    let enter = read_until(&mut first, |event| {
+      event.event_type().unwrap() == "ENTER" && event.peer_uuid().unwrap() == second_uuid
+    });
+    assert_eq!(enter.peer_name().unwrap(), "node-b");
+    assert_eq!(enter.display_name().unwrap(), "Kitchen Display");
+
+    let first_uuid = first.uuid().unwrap().to_string();
+    let enter = read_until(&mut second, |event| {
+      event.event_type().unwrap() == "ENTER" && event.peer_uuid().unwrap() == first_uuid
+    });
+    assert_eq!(enter.display_name().unwrap(), "first");
+  }
+
+  #[test]
+  fn app_id_filters_foreign_peers() {
+    let port = unique_port();
+    let mut node = ZyreBuilder::new().name("node").port(port).app_id("one").build_started().unwrap();
+    let _friend = ZyreBuilder::new().name("friend").port(port).app_id("one").build_started().unwrap();
+    let _stranger = ZyreBuilder::new().name("stranger").port(port).app_id("two").build_started().unwrap();
+
+    let mut names = Vec::new();
+    let deadline = Instant::now() + Duration::from_secs(3);
+    while Instant::now() < deadline {
+      if let Some(event) = node.read_event_timeout(Duration::from_millis(100)).unwrap() {
+        names.push(event.peer_name().unwrap().to_string());
+      }
+    }
+
+    assert!(names.contains(&"friend".to_string()));
+    assert!(!names.contains(&"stranger".to_string()));
+    assert_eq!(node.peers().unwrap().len(), 1);
+    assert!(node.stats().foreign > 0);
+  }
+
+  #[test]
+  fn gossip_ready() {
+    let id = unique_port();
+    let hub_endpoint = format!("inproc://gossip-hub-{}", id);
+    let _hub = ZyreBuilder::new().name("hub").endpoint(format!("inproc://hub-{}", id)).gossip_bind(&hub_endpoint).build_started().unwrap();
+    let mut client = ZyreBuilder::new().name("client").endpoint(format!("inproc://client-{}", id)).gossip_connect(&hub_endpoint).build_started().unwrap();
+
+    assert!(client.wait_for_gossip_ready(Duration::from_secs(5)).unwrap());
+    assert_eq!(client.read_event().unwrap().kind().unwrap(), EventType::Enter);
+  }
+
+  #[test]
+  fn multicast_address_rejects_broadcast() {
+    match ZyreBuilder::new().name("directed").port(unique_port()).multicast_address("127.255.255.255").build() {
+      Err(Error::InvalidAddress) => {},
+      other => panic!("expected InvalidAddress, got {:?}", other.err()),
+    }
+  }
+
+  #[test]
+  fn broadcast_address() {
+    // Without a broadcast-capable interface there is nothing to pick.
+    let address = match super::super::broadcast_interfaces().into_iter().next() {
+      Some((_, address)) => address,
+      None => return,
+    };
+
+    let mut zyre = ZyreBuilder::new().name("directed").port(unique_port()).broadcast_address(address).build().unwrap();
+    zyre.start().unwrap();
+    assert!(zyre.is_started());
+  }
+
+  #[test]
+  fn broadcast_address_unknown() {
+    match ZyreBuilder::new().name("directed").port(unique_port()).broadcast_address("192.0.2.255").build() {
+      Err(Error::InvalidAddress) => {},
+      other => panic!("expected InvalidAddress, got {:?}", other.err()),
+    }
+  }
+
+  #[test]
+  fn ipv4_discovery() {
+    let port = unique_port();
+    let mut first = ZyreBuilder::new().name("first").port(port).address_family(AddressFamily::V4).build().unwrap();
+    let mut second = ZyreBuilder::new().name("second").port(port).address_family(AddressFamily::V4).build().unwrap();
+    first.start().unwrap();
+    second.start().unwrap();
+
+    let second_uuid = second.uuid().unwrap().to_string();
+    loop {
+      let event = first.read_event().unwrap();
+
+      if event.event_type().unwrap() == "ENTER" && event.peer_uuid().unwrap() == second_uuid {
+        break;
+      }
+    }
+  }
+}
@@ -0,0 +1,101 @@
+use std::thread;
+use std::time::{ Duration, Instant };
+use super::Zyre;
+
+/// Where the node's timeout helpers (`read_event_timeout`, `wait_for_peer`,
+/// `ping`, `scatter_gather`, `join_retry` and the like) get the time from.
+/// Tests can swap in a clock they control with `Zyre::set_clock`, so
+/// timeouts expire without waiting on wall time.
+pub trait Clock {
+  fn now(&self) -> Instant;
+
+  /// Longest a single poll of libzyre may block while `remaining` is left
+  /// before a deadline. A clock that doesn't follow wall time should return
+  /// zero, so helpers only check for events and then consult `now` again.
+  fn wait_slice(&self, remaining:Duration) -> Duration {
+    remaining
+  }
+
+  /// Pauses a helper that polls rather than waits on libzyre.
+  fn sleep(&self, duration:Duration) {
+    thread::sleep(duration);
+  }
+}
+
+/// Wall time; what every node uses unless told otherwise.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  fn now(&self) -> Instant {
+    Instant::now()
+  }
+}
+
+impl Zyre {
+  /// Replaces the clock the timeout helpers use.
+  pub fn set_clock<C>(&mut self, clock:C) where C:Clock + 'static {
+    self.clock = Box::new(clock);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::cell::Cell;
+  use std::rc::Rc;
+  use super::super::{ Error, ZyreBuilder };
+  use super::super::tests::unique_port;
+
+  /// Moves forward by `step` every time it is read.
+  #[derive(Clone)]
+  struct FakeClock {
+    now: Rc<Cell<Instant>>,
+    step: Duration,
+  }
+
+  impl Clock for FakeClock {
+    fn now(&self) -> Instant {
+      let now = self.now.get();
+      self.now.set(now + self.step);
+      now
+    }
+
+    fn wait_slice(&self, _remaining:Duration) -> Duration {
+      Duration::from_millis(0)
+    }
+
+    fn sleep(&self, _duration:Duration) {}
+  }
+
+  #[test]
+  fn fake_clock_timeouts() {
+    let start = Instant::now();
+    let clock = FakeClock { now: Rc::new(Cell::new(start)), step: Duration::from_secs(60) };
+    let mut zyre = ZyreBuilder::new().name("alone").port(unique_port()).build_started().unwrap();
+    zyre.set_clock(clock.clone());
+
+    assert!(zyre.read_event_timeout(Duration::from_secs(3600)).unwrap().is_none());
+    assert!(clock.now.get() >= start + Duration::from_secs(3600));
+    assert!(!zyre.wait_for_peer("0123456789ABCDEF0123456789ABCDEF", Duration::from_secs(3600)).unwrap());
+    assert!(start.elapsed() < Duration::from_secs(5));
+  }
+
+  #[test]
+  fn fake_clock_helpers() {
+    let start = Instant::now();
+    let clock = FakeClock { now: Rc::new(Cell::new(start)), step: Duration::from_secs(60) };
+    let mut zyre = ZyreBuilder::new().name("alone").port(unique_port()).build_started().unwrap();
+    zyre.set_clock(clock);
+
+    match zyre.ping("0123456789ABCDEF0123456789ABCDEF", Duration::from_secs(3600)) {
+      Err(Error::Timeout) => {},
+      other => panic!("expected Timeout, got {:?}", other),
+    }
+    match zyre.join_retry("ALONE", 3, Duration::from_secs(3600)) {
+      Err(Error::JoinFailed) => {},
+      other => panic!("expected JoinFailed, got {:?}", other),
+    }
+    assert!(start.elapsed() < Duration::from_secs(5));
+  }
+}
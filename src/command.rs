@@ -0,0 +1,97 @@
+/// A typed value that travels as the string frames of a message.
+///
+/// Frames are encoded with `Message::from_frames` and decoded with
+/// `collect`, so they round-trip between nodes using this crate.
+///
+/// ```
+/// use zyre::Command;
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Light {
+///   On,
+///   Dim(u8),
+/// }
+///
+/// impl Command for Light {
+///   fn to_frames(&self) -> Vec<String> {
+///     match *self {
+///       Light::On => vec!["ON".to_string()],
+///       Light::Dim(level) => vec!["DIM".to_string(), level.to_string()],
+///     }
+///   }
+///
+///   fn from_frames(frames: &[&str]) -> Option<Light> {
+///     match frames {
+///       ["ON"] => Some(Light::On),
+///       ["DIM", level] => level.parse().ok().map(Light::Dim),
+///       _ => None,
+///     }
+///   }
+/// }
+///
+/// assert_eq!(Light::from_frames(&["DIM", "40"]), Some(Light::Dim(40)));
+/// ```
+pub trait Command: Sized {
+  fn to_frames(&self) -> Vec<String>;
+
+  /// Returns `None` if the frames don't describe a value of this type.
+  fn from_frames(frames:&[&str]) -> Option<Self>;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{ Zyre, ZyreBuilder };
+  use super::super::tests::{ read_until, unique_port };
+
+  #[derive(Debug, PartialEq)]
+  enum Job {
+    Start(String),
+    Stop,
+  }
+
+  impl Command for Job {
+    fn to_frames(&self) -> Vec<String> {
+      match *self {
+        Job::Start(ref name) => vec!["START".to_string(), name.clone()],
+        Job::Stop => vec!["STOP".to_string()],
+      }
+    }
+
+    fn from_frames(frames:&[&str]) -> Option<Job> {
+      match frames {
+        ["START", name] => Some(Job::Start(name.to_string())),
+        ["STOP"] => Some(Job::Stop),
+        _ => None,
+      }
+    }
+  }
+
+  fn started(name:&str, port:u16) -> Zyre {
+    let mut zyre = ZyreBuilder::new().name(name).port(port).build().unwrap();
+    zyre.start().unwrap();
+    zyre.join("JOBS").unwrap();
+    zyre
+  }
+
+  #[test]
+  fn shout_command_round_trip() {
+    let port = unique_port();
+    let mut sender = started("sender", port);
+    let mut receiver = started("receiver", port);
+
+    let receiver_uuid = receiver.uuid().unwrap().to_string();
+    read_until(&mut sender, |event| {
+      event.event_type().unwrap() == "JOIN" && event.peer_uuid().unwrap() == receiver_uuid
+    });
+
+    sender.shout_command("JOBS", &Job::Start("build".to_string())).unwrap();
+    sender.shout_command("JOBS", &Job::Stop).unwrap();
+
+    let first = read_until(&mut receiver, |event| event.event_type().unwrap() == "SHOUT");
+    assert_eq!(first.command::<Job>().unwrap(), Job::Start("build".to_string()));
+
+    let second = read_until(&mut receiver, |event| event.event_type().unwrap() == "SHOUT");
+    assert_eq!(second.command::<Job>().unwrap(), Job::Stop);
+  }
+}
@@ -0,0 +1,111 @@
+use std::io::{ Read, Write };
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use super::{ Error, Event, Message, Result };
+
+/// First frame of a message built by `Message::compressed`.
+const COMPRESSED_FRAME: &str = "$ZYRE-GZIP";
+/// Most bytes `Event::decompress_message` will inflate a payload to.
+pub const MAX_DECOMPRESSED: usize = 16 * 1024 * 1024;
+
+impl Message {
+  /// Builds a two-frame message holding `data` gzip-compressed: the marker
+  /// frame `$ZYRE-GZIP`, then the compressed bytes. Inflate it on the
+  /// receiving side with `Event::decompress_message`.
+  pub fn compressed(data:&[u8]) -> Message {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    // Writing into a Vec cannot fail.
+    encoder.write_all(data).unwrap();
+    let payload = encoder.finish().unwrap();
+
+    let mut msg = Message::new();
+    msg.push_bytes(&payload);
+    msg.push(COMPRESSED_FRAME).unwrap();
+    msg
+  }
+}
+
+impl Event {
+  /// Inflates a message built by `Message::compressed`, without detaching
+  /// it. Returns `None` if the event has no message or it isn't compressed;
+  /// fails with `Error::DecompressFailed` if the payload is corrupt or
+  /// inflates to more than `MAX_DECOMPRESSED` bytes.
+  pub fn decompress_message(&self) -> Result<Option<Vec<u8>>> {
+    self.decompress_message_limited(MAX_DECOMPRESSED)
+  }
+
+  /// `decompress_message` with a cap of `limit` bytes in place of
+  /// `MAX_DECOMPRESSED`, since a few KB from a peer can inflate to far more.
+  pub fn decompress_message_limited(&self, limit:usize) -> Result<Option<Vec<u8>>> {
+    let message = match self.message_ref() {
+      Some(message) => message,
+      None => return Ok(None),
+    };
+
+    match message.frames().as_slice() {
+      [marker, payload] if *marker == COMPRESSED_FRAME.as_bytes() => {
+        let mut data = Vec::new();
+        GzDecoder::new(*payload).take(limit as u64 + 1).read_to_end(&mut data).map_err(|_| Error::DecompressFailed)?;
+        if data.len() > limit {
+          return Err(Error::DecompressFailed);
+        }
+        Ok(Some(data))
+      },
+      _ => Ok(None),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{ EventType, ZyreBuilder };
+  use super::super::tests::{ read_until, unique_port };
+
+  #[test]
+  fn compressed_round_trip() {
+    let port = unique_port();
+    let mut sender = ZyreBuilder::new().name("sender").port(port).join("ZIP").build_started().unwrap();
+    let mut receiver = ZyreBuilder::new().name("receiver").port(port).join("ZIP").build_started().unwrap();
+    read_until(&mut sender, |event| event.kind().unwrap() == EventType::Join);
+
+    let data = "all work and no play makes jack a dull boy\n".repeat(1000).into_bytes();
+    let message = Message::compressed(&data);
+    assert!(message.content_size() < data.len());
+    sender.shout("ZIP", message).unwrap();
+
+    let event = read_until(&mut receiver, |event| event.kind().unwrap() == EventType::Shout);
+    assert_eq!(event.decompress_message().unwrap(), Some(data));
+  }
+
+  #[test]
+  fn uncompressed_message() {
+    let port = unique_port();
+    let mut sender = ZyreBuilder::new().name("sender").port(port).join("ZIP").build_started().unwrap();
+    let mut receiver = ZyreBuilder::new().name("receiver").port(port).join("ZIP").build_started().unwrap();
+    read_until(&mut sender, |event| event.kind().unwrap() == EventType::Join);
+
+    sender.shout("ZIP", Message::from_frames(vec!["plain"]).unwrap()).unwrap();
+
+    let event = read_until(&mut receiver, |event| event.kind().unwrap() == EventType::Shout);
+    assert_eq!(event.decompress_message().unwrap(), None);
+  }
+
+  #[test]
+  fn over_limit_payload() {
+    let port = unique_port();
+    let mut sender = ZyreBuilder::new().name("sender").port(port).join("ZIP").build_started().unwrap();
+    let mut receiver = ZyreBuilder::new().name("receiver").port(port).join("ZIP").build_started().unwrap();
+    read_until(&mut sender, |event| event.kind().unwrap() == EventType::Join);
+
+    sender.shout("ZIP", Message::compressed(&vec![0; 64 * 1024])).unwrap();
+
+    let event = read_until(&mut receiver, |event| event.kind().unwrap() == EventType::Shout);
+    match event.decompress_message_limited(1024) {
+      Err(Error::DecompressFailed) => {},
+      other => panic!("expected DecompressFailed, got {:?}", other),
+    }
+    assert_eq!(event.decompress_message().unwrap().map(|data| data.len()), Some(64 * 1024));
+  }
+}
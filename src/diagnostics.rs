@@ -0,0 +1,108 @@
+use std::fmt;
+use std::time::Duration;
+use super::{ DiscoveryMode, Stats, Zyre };
+
+/// A snapshot of a node's state from `Zyre::diagnostics`, for health dumps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostics {
+  pub name: String,
+  pub uuid: String,
+  /// `None` while stopped.
+  pub uptime: Option<Duration>,
+  pub discovery: DiscoveryMode,
+  pub peers: usize,
+  /// Sorted by name.
+  pub groups: Vec<String>,
+  pub stats: Stats,
+}
+
+impl Zyre {
+  /// Gathers the node's identity, discovery settings, peers, groups and
+  /// stats in one call. Anything libzyre fails to report is left empty
+  /// rather than failing the whole dump.
+  pub fn diagnostics(&self) -> Diagnostics {
+    let mut groups = self.own_groups().unwrap_or_default();
+    groups.sort();
+
+    Diagnostics {
+      name: self.name().map(|name| name.into_owned()).unwrap_or_default(),
+      uuid: self.uuid().map(String::from).unwrap_or_default(),
+      uptime: self.uptime(),
+      discovery: self.discovery_mode(),
+      peers: self.peers().map(|peers| peers.len()).unwrap_or(0),
+      groups,
+      stats: self.stats(),
+    }
+  }
+
+  /// The discovery settings the node was built or last switched with.
+  fn discovery_mode(&self) -> DiscoveryMode {
+    let endpoint = self.config.endpoint.clone().unwrap_or_default();
+
+    if let Some(ref hub) = self.config.gossip_bind {
+      DiscoveryMode::GossipHub { endpoint, hub: hub.clone() }
+    } else if let Some(hub) = self.config.gossip_connect.first() {
+      DiscoveryMode::GossipClient { endpoint, hub: hub.clone() }
+    } else {
+      DiscoveryMode::Beacon
+    }
+  }
+}
+
+impl fmt::Display for Diagnostics {
+  /// One `key: value` line per field.
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(formatter, "name: {}", self.name)?;
+    writeln!(formatter, "uuid: {}", self.uuid)?;
+    match self.uptime {
+      Some(uptime) => writeln!(formatter, "uptime: {:?}", uptime)?,
+      None => writeln!(formatter, "uptime: stopped")?,
+    }
+    match self.discovery {
+      DiscoveryMode::Beacon => writeln!(formatter, "discovery: beacon")?,
+      DiscoveryMode::GossipHub { ref hub, .. } => writeln!(formatter, "discovery: gossip hub on {}", hub)?,
+      DiscoveryMode::GossipClient { ref hub, .. } => writeln!(formatter, "discovery: gossip via {}", hub)?,
+    }
+    writeln!(formatter, "peers: {}", self.peers)?;
+    writeln!(formatter, "groups: {}", self.groups.join(", "))?;
+    write!(formatter, "stats: {} events, {} bytes, {} rejected, {} coalesced, {} foreign, {} muted",
+      self.stats.events, self.stats.bytes, self.stats.rejected, self.stats.coalesced, self.stats.foreign, self.stats.muted)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{ EventType, ZyreBuilder };
+  use super::super::tests::{ read_until, unique_port };
+
+  #[test]
+  fn started_node() {
+    let port = unique_port();
+    let mut zyre = ZyreBuilder::new().name("diagnosed").port(port).join("B").join("A").build_started().unwrap();
+    let _peer = ZyreBuilder::new().name("peer").port(port).build_started().unwrap();
+    read_until(&mut zyre, |event| event.kind().unwrap() == EventType::Enter);
+
+    let diagnostics = zyre.diagnostics();
+    assert_eq!(diagnostics.name, "diagnosed");
+    assert_eq!(diagnostics.uuid, zyre.uuid().unwrap());
+    assert!(diagnostics.uptime.is_some());
+    assert_eq!(diagnostics.discovery, DiscoveryMode::Beacon);
+    assert_eq!(diagnostics.peers, 1);
+    assert_eq!(diagnostics.groups, vec!["A", "B"]);
+    assert_eq!(diagnostics.stats, zyre.stats());
+
+    let dump = diagnostics.to_string();
+    assert!(dump.contains("name: diagnosed\n"), "{}", dump);
+    assert!(dump.contains("groups: A, B\n"), "{}", dump);
+    assert!(dump.contains("discovery: beacon\n"), "{}", dump);
+  }
+
+  #[test]
+  fn stopped_node() {
+    let zyre = ZyreBuilder::new().name("idle").build().unwrap();
+    let diagnostics = zyre.diagnostics();
+    assert_eq!(diagnostics.uptime, None);
+    assert!(diagnostics.to_string().contains("uptime: stopped\n"));
+  }
+}
@@ -0,0 +1,164 @@
+use std::collections::{ BTreeSet, HashMap };
+use std::time::Duration;
+use super::{ timestamp, whisper_frames, Error, Event, Message, Result, Zyre };
+
+/// First frame of a whisper asking a peer which headers it saw from us.
+const PROBE_FRAME: &str = "$ZYRE-HEADERS?";
+/// First frame of the answer to a header probe.
+const OBSERVED_FRAME: &str = "$ZYRE-HEADERS";
+/// Header libzyre advertises itself once the node has a cert.
+const PUBLIC_KEY_HEADER: &str = "X-PUBLICKEY";
+
+/// A header whose value this node set differs from what a peer received.
+/// `None` means the header was missing on that side.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeaderDiscrepancy {
+  pub name: String,
+  pub advertised: Option<String>,
+  pub observed: Option<String>,
+}
+
+impl Zyre {
+  /// Asks `peer` which headers it saw on this node's ENTER and lists every
+  /// one that differs from what this node advertises, sorted by name. That
+  /// includes the `X-PUBLICKEY` libzyre adds for an `identity_file`. The peer must be answering probes (see
+  /// `enable_ping_responder` and `answer_header_probe`); one that hasn't
+  /// seen this node's ENTER yet reports no headers at all. Events read
+  /// while waiting are kept and returned by later reads.
+  pub fn header_discrepancies(&mut self, peer:&str, timeout:Duration) -> Result<Vec<HeaderDiscrepancy>> {
+    let nonce = timestamp();
+    // `from_frames` pushes each frame to the front, so list them backwards.
+    self.whisper(peer, Message::from_frames(vec![nonce.as_str(), PROBE_FRAME])?)?;
+
+    let deadline = self.clock.now() + timeout;
+    let mut skipped = Vec::new();
+    let result = loop {
+      let now = self.clock.now();
+      if now >= deadline {
+        break Err(Error::Timeout);
+      }
+
+      match self.recv_event_timeout(deadline - now) {
+        Ok(Some(event)) => {
+          if let Some(observed) = observed_headers(&event, peer, &nonce) {
+            break Ok(observed);
+          }
+          skipped.push(event);
+        },
+        Ok(None) => {},
+        Err(err) => break Err(err),
+      }
+    };
+    self.pending.extend(skipped);
+    let observed = result?;
+
+    let advertised = self.advertised_headers();
+    let names:BTreeSet<&String> = advertised.keys().chain(observed.keys()).collect();
+    Ok(names.into_iter()
+      .filter_map(|name| {
+        let advertised = advertised.get(name);
+        let seen = observed.get(name);
+        if advertised == seen {
+          return None;
+        }
+
+        Some(HeaderDiscrepancy { name: name.clone(), advertised: advertised.cloned(), observed: seen.cloned() })
+      })
+      .collect())
+  }
+
+  /// The headers set through the builder and `set_header_bytes`, plus those
+  /// libzyre adds itself, so a correctly configured peer shows no
+  /// discrepancy for them.
+  fn advertised_headers(&self) -> HashMap<String, String> {
+    let mut headers:HashMap<String, String> = self.config.headers.iter()
+      .map(|(name, value)| (name.clone(), value.clone()))
+      .collect();
+
+    if let Some(ref key) = self.public_key {
+      headers.insert(PUBLIC_KEY_HEADER.to_string(), key.clone());
+    }
+
+    headers
+  }
+
+  /// Replies to `event` if it is a probe from `Zyre::header_discrepancies`,
+  /// with the headers this node saw on the sender's ENTER. Returns whether
+  /// it was one.
+  pub fn answer_header_probe(&mut self, event:&Event) -> Result<bool> {
+    let frames = match whisper_frames(event, PROBE_FRAME) {
+      Some(frames) => frames,
+      None => return Ok(false),
+    };
+    let nonce = match frames.get(1).and_then(|frame| String::from_utf8(frame.clone()).ok()) {
+      Some(nonce) => nonce,
+      None => return Ok(false),
+    };
+
+    let peer = event.peer_uuid()?.to_string();
+    let mut headers:Vec<(String, String)> = self.known.get(&peer)
+      .map(|known| known.headers.iter().map(|(name, value)| (name.clone(), value.clone())).collect())
+      .unwrap_or_default();
+    headers.sort();
+
+    let mut reply = vec![OBSERVED_FRAME.to_string(), nonce];
+    for (name, value) in headers {
+      reply.push(name);
+      reply.push(value);
+    }
+    reply.reverse();
+    self.whisper(&peer, Message::from_frames(reply)?)?;
+
+    Ok(true)
+  }
+}
+
+/// The headers carried by `event` if it answers the probe `nonce` sent to
+/// `peer`: `[$ZYRE-HEADERS, nonce, name, value, ...]`.
+fn observed_headers(event:&Event, peer:&str, nonce:&str) -> Option<HashMap<String, String>> {
+  let frames = whisper_frames(event, OBSERVED_FRAME)?;
+  if event.peer_uuid().ok() != Some(peer) || frames.get(1)?.as_slice() != nonce.as_bytes() {
+    return None;
+  }
+
+  let mut headers = HashMap::new();
+  for pair in frames[2..].chunks(2) {
+    if let [name, value] = pair {
+      headers.insert(String::from_utf8(name.clone()).ok()?, String::from_utf8(value.clone()).ok()?);
+    }
+  }
+
+  Some(headers)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+  use super::super::{ EventType, ZyreActor, ZyreBuilder };
+  use super::super::tests::{ read_until, unique_port };
+
+  #[test]
+  fn no_discrepancies() {
+    let port = unique_port();
+    let _responder = ZyreActor::spawn(move || {
+      let mut zyre = ZyreBuilder::new().name("responder").port(port).build()?;
+      zyre.enable_ping_responder();
+      Ok(zyre)
+    }).unwrap();
+
+    // Headers the library sets itself count as advertised too.
+    let identity = std::env::temp_dir().join(format!("zyre-probe-identity-{}-{}", std::process::id(), port));
+    let mut zyre = ZyreBuilder::new().name("prober").port(port)
+      .header("X-ROLE", "primary")
+      .display_name("Prober")
+      .protocol_version(2)
+      .identity_file(&identity)
+      .build_started().unwrap();
+    let enter = read_until(&mut zyre, |event| event.kind().unwrap() == EventType::Enter);
+
+    let discrepancies = zyre.header_discrepancies(enter.peer_uuid().unwrap(), Duration::from_secs(5)).unwrap();
+    assert_eq!(discrepancies, vec![]);
+    std::fs::remove_file(&identity).ok();
+    std::fs::remove_file(format!("{}_secret", identity.display())).ok();
+  }
+}
@@ -0,0 +1,73 @@
+use serde_json::{ Map, Value };
+use super::{ base64_encode, OwnedEvent };
+
+impl OwnedEvent {
+  /// One line of JSON describing the event, for structured logs. Keys are
+  /// sorted, so equal events always give equal lines: `address`, `group`
+  /// (`null` when absent), `headers`, `kind`, `message` (each frame in wire
+  /// order as standard base64), `name` and `peer` (the UUID).
+  pub fn to_json(&self) -> String {
+    let mut headers = Map::new();
+    for (name, value) in &self.headers {
+      headers.insert(name.clone(), Value::String(value.clone()));
+    }
+
+    let mut object = Map::new();
+    object.insert("address".to_string(), self.peer_addr.clone().map(Value::String).unwrap_or(Value::Null));
+    object.insert("group".to_string(), self.group.clone().map(Value::String).unwrap_or(Value::Null));
+    object.insert("headers".to_string(), Value::Object(headers));
+    object.insert("kind".to_string(), Value::String(self.event_type.clone()));
+    object.insert("message".to_string(), Value::Array(self.frames.iter().map(|frame| Value::String(base64_encode(frame))).collect()));
+    object.insert("name".to_string(), Value::String(self.peer_name.clone()));
+    object.insert("peer".to_string(), Value::String(self.peer_uuid.clone()));
+
+    // A Value with string keys always serializes.
+    serde_json::to_string(&Value::Object(object)).unwrap()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+  use super::super::OwnedEvent;
+
+  #[test]
+  fn enter_to_json() {
+    let mut headers = HashMap::new();
+    headers.insert("X-ROLE".to_string(), "primary".to_string());
+    headers.insert("X-APP".to_string(), "demo".to_string());
+    let enter = OwnedEvent {
+      event_type: "ENTER".to_string(),
+      peer_uuid: "0123456789ABCDEF0123456789ABCDEF".to_string(),
+      peer_name: "first".to_string(),
+      peer_addr: Some("tcp://192.168.1.20:49152".to_string()),
+      headers,
+      group: None,
+      frames: Vec::new(),
+    };
+
+    assert_eq!(enter.to_json(), concat!(
+      r#"{"address":"tcp://192.168.1.20:49152","group":null,"#,
+      r#""headers":{"X-APP":"demo","X-ROLE":"primary"},"kind":"ENTER","message":[],"#,
+      r#""name":"first","peer":"0123456789ABCDEF0123456789ABCDEF"}"#,
+    ));
+  }
+
+  #[test]
+  fn shout_to_json() {
+    let shout = OwnedEvent {
+      event_type: "SHOUT".to_string(),
+      peer_uuid: "0123456789ABCDEF0123456789ABCDEF".to_string(),
+      peer_name: "first".to_string(),
+      peer_addr: None,
+      headers: HashMap::new(),
+      group: Some("CHAT".to_string()),
+      frames: vec![b"hi".to_vec(), vec![0, 255]],
+    };
+
+    assert_eq!(shout.to_json(), concat!(
+      r#"{"address":null,"group":"CHAT","headers":{},"kind":"SHOUT","message":["aGk=","AP8="],"#,
+      r#""name":"first","peer":"0123456789ABCDEF0123456789ABCDEF"}"#,
+    ));
+  }
+}
@@ -1,20 +1,125 @@
 extern crate zyre_sys;
 
+mod actor;
+mod bridge;
+mod builder;
+mod clock;
+mod command;
+mod diagnostics;
+mod headers;
+mod run;
+mod tracker;
+mod transfer;
+mod watchdog;
+#[cfg(feature = "async")]
+mod stream;
+#[cfg(feature = "compression")]
+mod compression;
+#[cfg(feature = "repl")]
+mod repl;
+#[cfg(feature = "serde")]
+mod json;
+
+use std::borrow::Cow;
+use std::cmp;
+use std::collections::{ BTreeSet, HashMap, HashSet, VecDeque };
 use std::error;
 use std::ffi::{ CStr, CString };
 use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+use std::net::{ IpAddr, Ipv4Addr, Ipv6Addr, TcpListener };
+use std::mem;
+use std::ops::Range;
+use std::panic::{ self, AssertUnwindSafe };
+use std::os::raw::{ c_char, c_int, c_void };
+use std::path::Path;
+use std::ptr;
 use std::result;
+use std::slice;
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::time::{ Duration, Instant, SystemTime, UNIX_EPOCH };
 use zyre_sys::{ zmsg_t, zyre_t };
 
+pub use actor::{ ThreadOptions, ZyreActor, ZyreHandle };
+pub use bridge::Bridge;
+pub use builder::{ AddressFamily, DiscoveryMode, ZyreBuilder };
+pub use clock::{ Clock, SystemClock };
+pub use command::Command;
+pub use diagnostics::Diagnostics;
+pub use headers::HeaderDiscrepancy;
+pub use run::RunOptions;
+pub use tracker::{ DebouncedMembership, GroupTracker, PartitionDetector };
+pub use transfer::FileReceiver;
+pub use watchdog::{ ReconnectPolicy, Watchdog };
+#[cfg(feature = "async")]
+pub use stream::{ EventStream, OwnedEventStream };
+#[cfg(feature = "compression")]
+pub use compression::MAX_DECOMPRESSED;
+
+use builder::{ Config, APP_HEADER, DISPLAY_NAME_HEADER, PROTO_HEADER };
+
 pub type Result<T> = result::Result<T, Error>;
 
 pub enum Error {
   ToCString(std::ffi::NulError),
   FromCStr(std::str::Utf8Error),
-  StartFailed,
+  Io(io::Error),
+  /// Carries the OS error code observed when the start failed, if any.
+  /// libzyre binds its sockets on an internal thread, so this is best-effort.
+  StartFailed(Option<i32>),
+  NotStarted,
   JoinFailed,
   LeaveFailed,
   ReadInterrupted,
+  ActorStopped,
+  Timeout,
+  InvalidCommand,
+  SpawnFailed,
+  InvalidFrame,
+  NotARequest,
+  /// libzyre has no route to the node itself, so such whispers would be
+  /// silently lost.
+  WhisperToSelf,
+  /// Bytes passed to `OwnedEvent::decode` are truncated or malformed, or a
+  /// header read with `Event::header_bytes` isn't valid base64.
+  InvalidEncoding,
+  /// libzyre returned an event without a type, or without a field the
+  /// accessor needs.
+  MalformedEvent,
+  EndpointFailed,
+  /// `zyre_new` returned NULL when given the node's name. A name holding a
+  /// NUL byte fails earlier, with `Error::ToCString`.
+  InvalidName,
+  /// `zyre_new` returned NULL for an unnamed node, which only happens when
+  /// libzyre can't allocate the node or its actor thread.
+  AllocationFailed,
+  /// The group name is longer than `MAX_GROUP_LEN` bytes.
+  InvalidGroup,
+  /// The `ZyreBuilder::identity_file` cert couldn't be loaded or saved.
+  IdentityFailed,
+  /// The `ZyreBuilder::multicast_address` isn't an IPv4 multicast group, or
+  /// no interface broadcasts to the `ZyreBuilder::broadcast_address`.
+  InvalidAddress,
+  /// A `Zyre::run_with` handler panicked and `RunOptions::stop_on_panic` is
+  /// set. The message is in `Zyre::last_panic`.
+  HandlerPanicked,
+  /// A message failed `Event::validate_message`.
+  UnexpectedFrames { expected: usize, actual: usize },
+  /// A compressed message's payload could not be inflated.
+  #[cfg(feature = "compression")]
+  DecompressFailed,
+}
+
+impl Error {
+  /// The OS's description of the error code captured with the failure, if
+  /// any, for logging.
+  pub fn os_reason(&self) -> Option<String> {
+    match *self {
+      Error::StartFailed(Some(errno)) => Some(io::Error::from_raw_os_error(errno).to_string()),
+      _ => None,
+    }
+  }
 }
 
 impl error::Error for Error {
@@ -22,10 +127,31 @@ impl error::Error for Error {
     match *self {
       Error::ToCString(ref inner) => inner.description(),
       Error::FromCStr(ref inner) => inner.description(),
-      Error::StartFailed => "Zyre node failed to start",
+      Error::Io(_) => "I/O operation failed",
+      Error::StartFailed(_) => "Zyre node failed to start",
+      Error::NotStarted => "Zyre node has not been started",
       Error::JoinFailed => "Failed to join Zyre group",
       Error::LeaveFailed => "Failed to leave Zyre group",
       Error::ReadInterrupted => "Read was interrupted",
+      Error::ActorStopped => "Zyre actor has stopped",
+      Error::Timeout => "Timed out waiting for a response",
+      Error::InvalidCommand => "Message does not hold the expected command",
+      Error::SpawnFailed => "Failed to spawn a thread",
+      Error::InvalidFrame => "Frame is missing or has the wrong size",
+      Error::NotARequest => "Event is not a request",
+      Error::WhisperToSelf => "Cannot whisper to this node's own UUID",
+      Error::InvalidEncoding => "Encoded data is truncated or malformed",
+      Error::MalformedEvent => "Event is missing its type or a required field",
+      Error::EndpointFailed => "Failed to bind the node's endpoint",
+      Error::InvalidName => "Zyre node name was rejected",
+      Error::AllocationFailed => "Failed to allocate a Zyre node",
+      Error::InvalidGroup => "Group name is too long",
+      Error::IdentityFailed => "Failed to load or save the node's identity cert",
+      Error::InvalidAddress => "No interface matches the beacon address",
+      Error::HandlerPanicked => "Event handler panicked",
+      Error::UnexpectedFrames { .. } => "Message has the wrong number of frames",
+      #[cfg(feature = "compression")]
+      Error::DecompressFailed => "Compressed message payload is corrupt",
     }
   }
 }
@@ -33,14 +159,26 @@ impl error::Error for Error {
 impl fmt::Debug for Error {
   fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
     use std::error::Error;
-    write!(formatter, "{}", (*self).description())
+    match *self {
+      self::Error::Io(ref inner) => write!(formatter, "{:?}", inner),
+      self::Error::UnexpectedFrames { expected, actual } => {
+        write!(formatter, "Expected a message of {} frames, got {}", expected, actual)
+      },
+      _ => write!(formatter, "{}", (*self).description()),
+    }
   }
 }
 
 impl fmt::Display for Error {
   fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
     use std::error::Error;
-    write!(formatter, "{}", (*self).description())
+    match *self {
+      self::Error::Io(ref inner) => write!(formatter, "{}", inner),
+      self::Error::UnexpectedFrames { expected, actual } => {
+        write!(formatter, "Expected a message of {} frames, got {}", expected, actual)
+      },
+      _ => write!(formatter, "{}", (*self).description()),
+    }
   }
 }
 
@@ -56,28 +194,388 @@ impl std::convert::From<std::str::Utf8Error> for Error {
   }
 }
 
+impl std::convert::From<io::Error> for Error {
+  fn from(inner:io::Error) -> Error {
+    Error::Io(inner)
+  }
+}
+
+impl std::convert::From<Error> for io::Error {
+  fn from(inner:Error) -> io::Error {
+    let kind = match inner {
+      Error::Io(inner) => return inner,
+      Error::ToCString(_) | Error::WhisperToSelf | Error::InvalidName | Error::InvalidGroup | Error::InvalidAddress => io::ErrorKind::InvalidInput,
+      Error::FromCStr(_) | Error::InvalidEncoding | Error::MalformedEvent => io::ErrorKind::InvalidData,
+      Error::UnexpectedFrames { .. } => io::ErrorKind::InvalidData,
+      #[cfg(feature = "compression")]
+      Error::DecompressFailed => io::ErrorKind::InvalidData,
+      Error::StartFailed(Some(errno)) => io::Error::from_raw_os_error(errno).kind(),
+      Error::NotStarted => io::ErrorKind::NotConnected,
+      Error::ReadInterrupted => io::ErrorKind::Interrupted,
+      Error::ActorStopped => io::ErrorKind::BrokenPipe,
+      Error::Timeout => io::ErrorKind::TimedOut,
+      _ => io::ErrorKind::Other,
+    };
+
+    io::Error::new(kind, inner)
+  }
+}
+
+/// How string accessors treat frames and names that aren't valid UTF-8.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Utf8Policy {
+  /// Fail with `Error::FromCStr`.
+  #[default]
+  Strict,
+  /// Substitute U+FFFD for invalid sequences.
+  Lossy,
+}
+
+impl Utf8Policy {
+  fn decode<'a>(self, bytes:&'a [u8]) -> Result<Cow<'a, str>> {
+    match self {
+      Utf8Policy::Strict => Ok(Cow::Borrowed(std::str::from_utf8(bytes)?)),
+      Utf8Policy::Lossy => Ok(String::from_utf8_lossy(bytes)),
+    }
+  }
+}
+
+/// Counters kept by a node as it reads events.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+  /// Events read from libzyre, including ones the crate consumed itself.
+  pub events: u64,
+  /// Message content bytes received.
+  pub bytes: u64,
+  /// Messages dropped for exceeding `max_inbound_frame` or `max_frames`.
+  pub rejected: u64,
+  /// Shouts superseded by a newer one in a coalesced group.
+  pub coalesced: u64,
+  /// Events dropped because the peer has a different `ZyreBuilder::app_id`.
+  pub foreign: u64,
+  /// Shouts skipped because their group was muted with `Zyre::mute_group`.
+  pub muted: u64,
+}
+
+/// How long a peer may stay quiet before libzyre reports it, as set with
+/// `ZyreBuilder::evasive_timeout` and friends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timeouts {
+  /// Before an EVASIVE event.
+  pub evasive: Duration,
+  /// Before a SILENT event.
+  pub silent: Duration,
+  /// Before the peer is dropped with EXIT.
+  pub expired: Duration,
+}
+
+impl Default for Timeouts {
+  /// libzyre's defaults.
+  fn default() -> Timeouts {
+    Timeouts {
+      evasive: Duration::from_millis(5000),
+      silent: Duration::from_millis(5000),
+      expired: Duration::from_millis(30000),
+    }
+  }
+}
+
+/// A node's peer count and `Stats`, as shouted by `Zyre::shout_stats`.
+/// Decode one with `Event::command::<StatsReport>()`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StatsReport {
+  pub peers: usize,
+  pub stats: Stats,
+}
+
+const STATS_FRAME: &str = "$ZYRE-STATS";
+
+impl Command for StatsReport {
+  fn to_frames(&self) -> Vec<String> {
+    vec![
+      STATS_FRAME.to_string(),
+      format!("peers={}", self.peers),
+      format!("events={}", self.stats.events),
+      format!("bytes={}", self.stats.bytes),
+      format!("rejected={}", self.stats.rejected),
+      format!("coalesced={}", self.stats.coalesced),
+      format!("foreign={}", self.stats.foreign),
+      format!("muted={}", self.stats.muted),
+    ]
+  }
+
+  fn from_frames(frames:&[&str]) -> Option<StatsReport> {
+    if frames.first() != Some(&STATS_FRAME) {
+      return None;
+    }
+
+    let mut report = StatsReport::default();
+    for frame in &frames[1..] {
+      let mut parts = frame.splitn(2, '=');
+      let (key, value) = (parts.next()?, parts.next()?);
+
+      match key {
+        "peers" => report.peers = value.parse().ok()?,
+        "events" => report.stats.events = value.parse().ok()?,
+        "bytes" => report.stats.bytes = value.parse().ok()?,
+        "rejected" => report.stats.rejected = value.parse().ok()?,
+        "coalesced" => report.stats.coalesced = value.parse().ok()?,
+        "foreign" => report.stats.foreign = value.parse().ok()?,
+        "muted" => report.stats.muted = value.parse().ok()?,
+        // Reports from newer versions may carry counters we don't know.
+        _ => {},
+      }
+    }
+
+    Some(report)
+  }
+}
+
+/// Longest group name, in bytes, that `join`, `leave` and `shout` accept.
+/// ZRE sends group names with a one-byte length, so longer ones would be cut
+/// short on the wire and reach a different group.
+pub const MAX_GROUP_LEN: usize = 255;
+
+/// libzyre's beacon interval when none is set.
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(1000);
+
+const PING_FRAME: &str = "$ZYRE-PING";
+const PONG_FRAME: &str = "$ZYRE-PONG";
+const REQUEST_FRAME: &str = "$ZYRE-REQ";
+const REPLY_FRAME: &str = "$ZYRE-REP";
+
+static NEXT_CORRELATION: AtomicUsize = AtomicUsize::new(0);
+
+/// Called with the group and whether this node now leads it.
+type LeadershipCallback = Box<dyn FnMut(&str, bool)>;
+
+/// Answers a request with a reply, or `None` to send none.
+type RequestHandler = Box<dyn FnMut(&Message) -> Option<Message> + Send>;
+
 pub struct Zyre {
   sys: *mut zyre_t,
+  started_at: Option<Instant>,
+  config: Config,
+  pending: VecDeque<Event>,
+  clock: Box<dyn Clock>,
+  ping_responder: bool,
+  groups: BTreeSet<String>,
+  stats: Stats,
+  foreign: HashSet<String>,
+  previous_uuid: Option<String>,
+  membership: HashMap<String, BTreeSet<String>>,
+  leaders: HashMap<String, String>,
+  leadership_callbacks: Vec<LeadershipCallback>,
+  muted: HashSet<String>,
+  known: HashMap<String, Peer>,
+  request_handlers: HashMap<String, RequestHandler>,
+  public_key: Option<String>,
+  last_panic: Option<String>,
+  gossip_endpoint: Option<String>,
+  last_seen: HashMap<String, Instant>,
 }
 
 impl Zyre {
+  /// Fails with `Error::ToCString` if `name` holds a NUL byte, or
+  /// `Error::InvalidName` if libzyre won't create a node with it.
   pub fn new(name: Option<&str>) -> Result<Zyre> {
+    Zyre::from_config(Config {
+      name: name.map(String::from),
+      ..Config::default()
+    })
+  }
+
+  fn from_config(config:Config) -> Result<Zyre> {
     unsafe {
-      let sys = match name {
-        Some(value) => zyre_sys::zyre_new(CString::new(value)?.as_ptr()),
-        None => zyre_sys::zyre_new(0 as *mut _),
+      let mut zyre = Zyre {
+        sys: new_sys(&config)?,
+        started_at: None,
+        config,
+        pending: VecDeque::new(),
+        clock: Box::new(SystemClock),
+        ping_responder: false,
+        groups: BTreeSet::new(),
+        stats: Stats::default(),
+        foreign: HashSet::new(),
+        previous_uuid: None,
+        membership: HashMap::new(),
+        leaders: HashMap::new(),
+        leadership_callbacks: Vec::new(),
+        muted: HashSet::new(),
+        known: HashMap::new(),
+        request_handlers: HashMap::new(),
+        public_key: None,
+        last_panic: None,
+        gossip_endpoint: None,
+        last_seen: HashMap::new(),
       };
+      zyre.configure()?;
 
-      Ok(Zyre {
-        sys: sys,
-      })
+      Ok(zyre)
+    }
+  }
+
+  fn configure(&mut self) -> Result<()> {
+    unsafe {
+      if let Some(port) = self.config.port {
+        zyre_sys::zyre_set_port(self.sys, port as c_int);
+      }
+
+      match self.config.family {
+        Some(AddressFamily::V4) => zyre_sys::zsys_set_ipv6(0),
+        Some(AddressFamily::V6) => zyre_sys::zsys_set_ipv6(1),
+        None => {},
+      }
+
+      if let Some(interval) = self.config.interval {
+        zyre_sys::zyre_set_interval(self.sys, interval.as_millis() as usize);
+      }
+
+      let timeouts = self.config.timeouts;
+      zyre_sys::zyre_set_evasive_timeout(self.sys, timeout_ms(timeouts.evasive));
+      zyre_sys::zyre_set_silent_timeout(self.sys, timeout_ms(timeouts.silent));
+      zyre_sys::zyre_set_expired_timeout(self.sys, timeout_ms(timeouts.expired));
+
+      for group in &self.config.contest {
+        zyre_sys::zyre_set_contest_in_group(self.sys, CString::new(group.as_str())?.as_ptr());
+      }
+
+      if let Some(ref path) = self.config.identity_file {
+        self.public_key = Some(apply_identity(self.sys, path)?);
+      }
+
+      if let Some(ref address) = self.config.broadcast_address {
+        let (name, _) = broadcast_interfaces().into_iter()
+          .find(|(_, broadcast)| broadcast == address)
+          .ok_or(Error::InvalidAddress)?;
+        zyre_sys::zyre_set_interface(self.sys, CString::new(name)?.as_ptr());
+      }
+
+      if let Some(ref address) = self.config.multicast_address {
+        match address.parse::<Ipv4Addr>() {
+          Ok(group) if group.is_multicast() => {},
+          _ => return Err(Error::InvalidAddress),
+        }
+        zyre_sys::zsys_set_ipv4_mcast_address(CString::new(address.as_str())?.as_ptr());
+      }
+
+      for (name, value) in &self.config.headers {
+        let name = CString::new(name.as_str())?;
+        let value = CString::new(value.as_str())?;
+        zyre_sys::zyre_set_header(self.sys, name.as_ptr(), b"%s\0".as_ptr() as *const c_char, value.as_ptr());
+      }
+
+      if let Some(ref endpoint) = self.config.endpoint {
+        let endpoint = CString::new(endpoint.as_str())?;
+        if zyre_sys::zyre_set_endpoint(self.sys, b"%s\0".as_ptr() as *const c_char, endpoint.as_ptr()) != 0 {
+          return Err(Error::EndpointFailed);
+        }
+      }
+
+      self.gossip_endpoint = None;
+      if let Some(ref endpoint) = self.config.gossip_bind {
+        let (bind, advertised) = resolve_gossip_endpoint(endpoint)?;
+        let bind = CString::new(bind)?;
+        zyre_sys::zyre_gossip_bind(self.sys, b"%s\0".as_ptr() as *const c_char, bind.as_ptr());
+        self.gossip_endpoint = Some(advertised);
+      }
+
+      for endpoint in &self.config.gossip_connect {
+        let endpoint = CString::new(endpoint.as_str())?;
+        zyre_sys::zyre_gossip_connect(self.sys, b"%s\0".as_ptr() as *const c_char, endpoint.as_ptr());
+      }
     }
+
+    Ok(())
   }
 
   pub fn destroy(&mut self) {
     unsafe {
       zyre_sys::zyre_destroy(&mut self.sys);
     }
+    self.started_at = None;
+  }
+
+  /// Replaces the node with a fresh one built from the same configuration,
+  /// starts it and rejoins its groups. libzyre gives the new node a new
+  /// UUID; the old one is kept in `previous_uuid`. Unread events are
+  /// discarded.
+  ///
+  /// If it fails, the groups are kept for the next attempt, and the node may
+  /// be left without an underlying libzyre node until a `restart` succeeds.
+  pub fn restart(&mut self) -> Result<()> {
+    // A failed restart can leave no node to take a UUID from.
+    if !self.sys.is_null() {
+      self.previous_uuid = Some(self.uuid()?.to_string());
+    }
+
+    let groups = mem::take(&mut self.groups);
+    let result = self.rebuild(&groups);
+    if result.is_err() {
+      self.groups = groups;
+    }
+
+    result
+  }
+
+  fn rebuild(&mut self, groups:&BTreeSet<String>) -> Result<()> {
+    self.destroy();
+    self.sys = unsafe { new_sys(&self.config)? };
+    self.configure()?;
+    self.reset_state();
+
+    self.start()?;
+    for group in groups {
+      self.join(group)?;
+    }
+
+    Ok(())
+  }
+
+  /// Moves the node to another way of discovering peers. libzyre only reads
+  /// discovery settings at start, so this replaces the node as `restart`
+  /// does: it comes back started, rejoined to its groups, under a new UUID.
+  pub fn switch_discovery(&mut self, mode:DiscoveryMode) -> Result<()> {
+    let (endpoint, bind, connect) = match mode {
+      DiscoveryMode::Beacon => (None, None, Vec::new()),
+      DiscoveryMode::GossipHub { endpoint, hub } => (Some(endpoint), Some(hub), Vec::new()),
+      DiscoveryMode::GossipClient { endpoint, hub } => (Some(endpoint), None, vec![hub]),
+    };
+    self.config.endpoint = endpoint;
+    self.config.gossip_bind = bind;
+    self.config.gossip_connect = connect;
+
+    self.restart()
+  }
+
+  /// Forgets everything learned about peers (what `groups_of_peer`,
+  /// `is_leader`, `peers_sorted_by_name` and `peer_last_seen` report, and
+  /// `app_id` verdicts)
+  /// along with unread events, so it repopulates from fresh ENTER and JOIN
+  /// events. `restart` calls this; the node's own groups and stats are kept.
+  pub fn reset_state(&mut self) {
+    self.pending.clear();
+    self.foreign.clear();
+    self.membership.clear();
+    self.leaders.clear();
+    self.known.clear();
+    self.last_seen.clear();
+  }
+
+  /// The UUID the node had before the last `restart`.
+  pub fn previous_uuid(&self) -> Option<&str> {
+    self.previous_uuid.as_deref()
+  }
+
+  /// The underlying libzyre node, still owned by this `Zyre`.
+  pub fn as_raw(&self) -> *mut zyre_t {
+    self.sys
+  }
+
+  /// Gives up ownership of the underlying libzyre node. The caller must stop
+  /// and destroy it with `zyre_sys`.
+  pub fn into_raw(mut self) -> *mut zyre_t {
+    mem::replace(&mut self.sys, ptr::null_mut())
   }
 
   pub fn uuid(&self) -> Result<&str> {
@@ -86,19 +584,55 @@ impl Zyre {
     }
   }
 
-  pub fn name(&self) -> Result<&str> {
+  pub fn name(&self) -> Result<Cow<'_, str>> {
     unsafe {
-      Ok(CStr::from_ptr(zyre_sys::zyre_name(self.sys)).to_str()?)
+      self.config.utf8.decode(CStr::from_ptr(zyre_sys::zyre_name(self.sys)).to_bytes())
     }
   }
 
+  /// The public half of the `ZyreBuilder::identity_file` cert, in Z85 text,
+  /// or `None` if the node was built without one.
+  pub fn public_key(&self) -> Option<&str> {
+    self.public_key.as_deref()
+  }
+
+  /// Where other nodes should `gossip_connect` to reach this node's gossip
+  /// hub, or `None` if it doesn't run one. A `tcp://` endpoint given to
+  /// `ZyreBuilder::gossip_bind` with a `*` or `0` port is bound to a free
+  /// port picked when the node is built (again on every `restart`), and a
+  /// `*` host is reported as this machine's host name.
+  pub fn gossip_endpoint(&self) -> Option<String> {
+    self.gossip_endpoint.clone()
+  }
+
+  /// The beacon interval the node was built with, or libzyre's default of
+  /// one second.
+  pub fn effective_interval(&self) -> Duration {
+    self.config.interval.unwrap_or(DEFAULT_INTERVAL)
+  }
+
+  /// The timeouts the node was built with, or libzyre's defaults.
+  pub fn timeouts(&self) -> Timeouts {
+    self.config.timeouts
+  }
+
+  pub fn utf8_policy(&self) -> Utf8Policy {
+    self.config.utf8
+  }
+
+  /// Applies to `name` and to every event (and its message) read afterwards.
+  pub fn set_utf8_policy(&mut self, policy:Utf8Policy) {
+    self.config.utf8 = policy;
+  }
+
   pub fn start(&mut self) -> Result<()> {
     unsafe {
       let rc = zyre_sys::zyre_start(self.sys);
       if rc != 0 {
-        // TODO(schoon) - Get the reason from Zyre.
-        Err(Error::StartFailed)
+        let errno = io::Error::last_os_error().raw_os_error().filter(|&code| code != 0);
+        Err(Error::StartFailed(errno))
       } else {
+        self.started_at = Some(Instant::now());
         Ok(())
       }
     }
@@ -108,308 +642,3677 @@ impl Zyre {
     unsafe {
       zyre_sys::zyre_stop(self.sys);
     }
+    self.started_at = None;
+  }
+
+  pub fn is_started(&self) -> bool {
+    self.started_at.is_some()
+  }
+
+  /// Time since the last successful `start`, or `None` while stopped.
+  pub fn uptime(&self) -> Option<Duration> {
+    self.started_at.map(|at| at.elapsed())
+  }
+
+  /// Checks the node's actor pipe can still be polled without error.
+  ///
+  /// This catches a destroyed or interrupted node, but an actor thread that is
+  /// alive and stuck still reports healthy: libzyre gives no way to ask it
+  /// for a response without risking the very hang being detected.
+  pub fn is_healthy(&self) -> bool {
+    if !self.is_started() || self.sys.is_null() {
+      return false;
+    }
+
+    unsafe {
+      if zyre_sys::zyre_socket(self.sys).is_null() {
+        return false;
+      }
+    }
+
+    self.poll(Duration::from_millis(0)).is_ok()
+  }
+
+  /// Advertises a binary header value. libzyre headers are strings, so the
+  /// value is sent as standard base64 (with `=` padding) for peers to read
+  /// back with `Event::header_bytes`. Like all headers it reaches peers from
+  /// the next start, and is kept across restarts.
+  pub fn set_header_bytes(&mut self, name:&str, value:&[u8]) -> Result<()> {
+    let encoded = base64_encode(value);
+    unsafe {
+      let name = CString::new(name)?;
+      let value = CString::new(encoded.as_str())?;
+      zyre_sys::zyre_set_header(self.sys, name.as_ptr(), b"%s\0".as_ptr() as *const c_char, value.as_ptr());
+    }
+    self.config.headers.insert(name.to_string(), encoded);
+
+    Ok(())
   }
 
   pub fn join<T>(&mut self, group:T) -> Result<()> where T:AsRef<str>{
+    check_group(group.as_ref())?;
+
     unsafe {
       let rc = zyre_sys::zyre_join(self.sys, CString::new(group.as_ref())?.as_ptr());
       if rc != 0 {
         // TODO(schoon) - Get the reason from Zyre.
         Err(Error::JoinFailed)
       } else {
+        self.groups.insert(group.as_ref().to_string());
         Ok(())
       }
     }
   }
 
+  /// Joins `group` and waits up to `delay` for another member to show up,
+  /// leaving and rejoining to send the JOIN again, up to `attempts` times in
+  /// all. Under gossip a JOIN sent before the hub connection is up can be
+  /// lost, so this gets pub/sub going once the first peer is reachable.
+  /// Fails with `Error::JoinFailed` if no member is seen; the node stays in
+  /// the group either way.
+  pub fn join_retry(&mut self, group:&str, attempts:usize, delay:Duration) -> Result<()> {
+    for attempt in 0..attempts {
+      if attempt > 0 {
+        self.leave(group)?;
+      }
+      self.join(group)?;
+
+      let deadline = self.clock.now() + delay;
+      loop {
+        if !self.peers_by_group(group)?.is_empty() {
+          return Ok(());
+        }
+
+        let now = self.clock.now();
+        if now >= deadline {
+          break;
+        }
+        self.clock.sleep(cmp::min(deadline - now, Duration::from_millis(10)));
+      }
+    }
+
+    Err(Error::JoinFailed)
+  }
+
   pub fn leave<T>(&mut self, group:T) -> Result<()> where T:AsRef<str> {
+    check_group(group.as_ref())?;
+
     unsafe {
       let rc = zyre_sys::zyre_leave(self.sys, CString::new(group.as_ref())?.as_ptr());
       if rc != 0 {
         // TODO(schoon) - Get the reason from Zyre.
         Err(Error::LeaveFailed)
       } else {
+        self.groups.remove(group.as_ref());
         Ok(())
       }
     }
   }
 
-  pub fn read_event(&mut self) -> Result<Event> {
-    unsafe {
-      let event = zyre_sys::zyre_event_new(self.sys);
+  /// Makes `desired` the node's groups, leaving groups not in it and joining
+  /// those not yet joined. Groups in both are left alone, so peers see no
+  /// churn for them. Stops at the first failed join or leave.
+  pub fn set_groups<'a, I>(&mut self, desired:I) -> Result<()> where I:IntoIterator<Item=&'a str> {
+    let desired:BTreeSet<&str> = desired.into_iter().collect();
+    let current = self.own_groups()?;
 
-      if event.is_null() {
-        Err(Error::ReadInterrupted)
-      } else {
-        Ok(Event::new(event))
-      }
+    for group in current.iter().filter(|group| !desired.contains(group.as_str())) {
+      self.leave(group)?;
     }
-  }
-
-  pub fn whisper<T>(&mut self, peer:T, mut msg:Message) -> Result<()> where T:AsRef<str> {
-    unsafe {
-      zyre_sys::zyre_whisper(self.sys, CString::new(peer.as_ref())?.as_ptr(), &mut msg.unwrap());
+    for group in desired.iter().filter(|group| !current.iter().any(|joined| joined == *group)) {
+      self.join(group)?;
     }
 
     Ok(())
   }
 
-  pub fn shout<T>(&mut self, group:T, mut msg:Message) -> Result<()> where T:AsRef<str> {
-    unsafe {
-      zyre_sys::zyre_shout(self.sys, CString::new(group.as_ref())?.as_ptr(), &mut msg.unwrap());
+  /// Leaves every joined group before stopping, so peers see a LEAVE for
+  /// each group instead of waiting for EXIT. The node is stopped even if a
+  /// leave fails; the first failure is returned.
+  pub fn leave_all_then_stop(&mut self) -> Result<()> {
+    let groups:Vec<String> = self.groups.iter().cloned().collect();
+    let mut result = Ok(());
+
+    for group in groups {
+      if let Err(err) = self.leave(&group) {
+        if result.is_ok() {
+          result = Err(err);
+        }
+      }
     }
+    self.stop();
 
-    Ok(())
+    result
   }
-}
 
-impl Drop for Zyre {
-  fn drop(&mut self) {
-    self.destroy();
-  }
-}
+  /// Joins each group in turn, carrying on past failures. Returns the groups
+  /// that were joined alongside every group that failed and why.
+  pub fn join_all<I, T>(&mut self, groups:I) -> (Vec<String>, Vec<(String, Error)>)
+  where I:IntoIterator<Item=T>, T:AsRef<str> {
+    let mut joined = Vec::new();
+    let mut failed = Vec::new();
 
-#[derive(Debug)]
-pub struct Event {
-  sys: *mut zyre_sys::zyre_event_t,
-}
+    for group in groups {
+      let group = group.as_ref();
 
-impl Event {
-  fn new(event:*mut zyre_sys::zyre_event_t) -> Event {
-    Event {
-      sys: event,
+      match self.join(group) {
+        Ok(()) => joined.push(group.to_string()),
+        Err(err) => failed.push((group.to_string(), err)),
+      }
     }
-  }
 
-  pub fn destroy(&mut self) {
-    unsafe {
-      zyre_sys::zyre_event_destroy(&mut self.sys);
-    }
+    (joined, failed)
   }
 
-  pub fn event_type(&self) -> Result<&str> {
-    unsafe {
-      Ok(CStr::from_ptr(zyre_sys::zyre_event_type(self.sys)).to_str()?)
-    }
+  /// UUIDs of every peer currently known to the node.
+  pub fn peers(&self) -> Result<Vec<String>> {
+    let peers = unsafe {
+      take_strings(zyre_sys::zyre_peers(self.sys))?
+    };
+
+    self.compatible(peers)
   }
 
-  pub fn peer_uuid(&self) -> Result<&str> {
+  /// The value of a header `peer` advertised, if it is known and set.
+  pub fn peer_header_value<P, N>(&self, peer:P, name:N) -> Result<Option<String>> where P:AsRef<str>, N:AsRef<str> {
     unsafe {
-      Ok(CStr::from_ptr(zyre_sys::zyre_event_peer_uuid(self.sys)).to_str()?)
+      let peer = CString::new(peer.as_ref())?;
+      let name = CString::new(name.as_ref())?;
+      take_string(zyre_sys::zyre_peer_header_value(self.sys, peer.as_ptr(), name.as_ptr()))
     }
   }
 
-  pub fn peer_name(&self) -> Result<&str> {
-    unsafe {
-      Ok(CStr::from_ptr(zyre_sys::zyre_event_peer_name(self.sys)).to_str()?)
-    }
+  /// How long since this node last read an event from `peer`, other than
+  /// EVASIVE or SILENT, which report its silence. `None` for peers it hasn't
+  /// read an event from, or that have left. Times come from the node's
+  /// `Clock`; the last-seen mark only moves as events are read.
+  pub fn peer_last_seen(&self, uuid:&str) -> Option<Duration> {
+    self.last_seen.get(uuid).map(|seen| self.clock.now().saturating_duration_since(*seen))
   }
 
-  pub fn peer_addr(&self) -> Result<&str> {
-    unsafe {
-      Ok(CStr::from_ptr(zyre_sys::zyre_event_peer_addr(self.sys)).to_str()?)
+  /// An ENTER-like event for every current peer, sorted by UUID, so a
+  /// consumer that starts late can build the same view as one that saw each
+  /// ENTER. Peers whose ENTER has been read carry its name, address and
+  /// headers; for the rest libzyre only offers the address, so the name is
+  /// empty and there are no headers.
+  pub fn replay_peers(&self) -> Result<Vec<OwnedEvent>> {
+    let mut peers = self.peers()?;
+    peers.sort();
+
+    let mut events = Vec::with_capacity(peers.len());
+    for uuid in peers {
+      let (name, address, headers) = match self.known.get(&uuid) {
+        Some(peer) => (peer.name.clone(), Some(peer.address.clone()), peer.headers.clone()),
+        None => (String::new(), self.peer_address(&uuid)?, HashMap::new()),
+      };
+
+      events.push(OwnedEvent {
+        event_type: EventType::Enter.to_string(),
+        peer_uuid: uuid,
+        peer_name: name,
+        peer_addr: address,
+        headers,
+        group: None,
+        frames: Vec::new(),
+      });
     }
+
+    Ok(events)
   }
 
-  pub fn group(&self) -> Result<&str> {
+  fn peer_address(&self, peer:&str) -> Result<Option<String>> {
     unsafe {
-      Ok(CStr::from_ptr(zyre_sys::zyre_event_group(self.sys)).to_str()?)
+      let peer = CString::new(peer)?;
+      take_string(zyre_sys::zyre_peer_address(self.sys, peer.as_ptr()))
     }
   }
 
-  pub fn message(&mut self) -> Message {
-    unsafe {
-      Message::from_ptr(zyre_sys::zyre_event_get_msg(self.sys))
+  /// Maps each peer advertising header `name` to its value, to see which
+  /// roles or capacities exist in the network. Peers without it are left
+  /// out.
+  pub fn peer_header_values(&self, name:&str) -> Result<HashMap<String, String>> {
+    let mut values = HashMap::new();
+
+    for peer in self.peers()? {
+      if let Some(value) = self.peer_header_value(&peer, name)? {
+        values.insert(peer, value);
+      }
     }
-  }
-}
 
-impl Drop for Event {
-  fn drop(&mut self) {
-    self.destroy();
+    Ok(values)
   }
-}
 
-#[derive(Debug)]
-pub struct Message {
-  sys: *mut zmsg_t,
-}
+  /// Peers advertising a `ZyreBuilder::protocol_version` of at least `min`.
+  /// Peers that advertise none, or an unparseable one, are left out.
+  pub fn compatible_peers(&self, min:u32) -> Result<Vec<String>> {
+    let mut compatible = Vec::new();
 
-impl Message {
-  pub fn new() -> Message {
-    Message {
-      sys:unsafe { zyre_sys::zmsg_new() },
+    for peer in self.peers()? {
+      let version = self.peer_header_value(&peer, PROTO_HEADER)?.and_then(|value| value.parse::<u32>().ok());
+      if version.is_some_and(|version| version >= min) {
+        compatible.push(peer);
+      }
     }
+
+    Ok(compatible)
   }
 
-  pub fn from_frames<T>(frames:Vec<T>) -> Result<Message>
-  where T:AsRef<str> {
-    let mut msg = Message::new();
+  /// Drops peers with a different `app_id`, if this node has one.
+  fn compatible(&self, peers:Vec<String>) -> Result<Vec<String>> {
+    let app_id = match self.config.app_id {
+      Some(ref app_id) => app_id,
+      None => return Ok(peers),
+    };
 
-    for frame in frames {
-      msg.push(frame)?;
+    let mut compatible = Vec::with_capacity(peers.len());
+    for peer in peers {
+      if self.peer_header_value(&peer, APP_HEADER)?.as_ref() == Some(app_id) {
+        compatible.push(peer);
+      }
     }
 
-    Ok(msg)
+    Ok(compatible)
   }
 
-  fn from_ptr(sys:*mut zmsg_t) -> Message {
-    Message {
-      sys:sys,
+  pub fn own_groups(&self) -> Result<Vec<String>> {
+    unsafe {
+      take_strings(zyre_sys::zyre_own_groups(self.sys))
     }
   }
 
-  pub fn destroy(&mut self) {
+  /// Groups known through connected peers.
+  pub fn peer_groups(&self) -> Result<Vec<String>> {
     unsafe {
-      zyre_sys::zmsg_destroy(&mut self.sys);
+      take_strings(zyre_sys::zyre_peer_groups(self.sys))
     }
   }
 
-  fn unwrap(&mut self) -> *mut zmsg_t {
-    let temp = self.sys;
-    self.sys = 0 as *mut _;
-    temp
-  }
+  /// Every group this node or any of its peers is in, sorted.
+  pub fn all_groups(&self) -> Result<Vec<String>> {
+    let mut groups:BTreeSet<String> = self.own_groups()?.into_iter().collect();
+    groups.extend(self.peer_groups()?);
 
-  pub fn size(&self) -> usize {
-    unsafe {
-      zyre_sys::zmsg_size(self.sys)
-    }
+    Ok(groups.into_iter().collect())
   }
 
-  pub fn push<T>(&mut self, frame:T) -> Result<()> where T:AsRef<str> {
-    unsafe {
-      zyre_sys::zmsg_pushstr(self.sys, CString::new(frame.as_ref())?.as_ptr());
-    }
+  /// Groups `uuid` has joined, sorted, as seen from the JOIN and LEAVE events
+  /// read so far. libzyre offers no direct query for this.
+  pub fn groups_of_peer(&self, uuid:&str) -> Result<Vec<String>> {
+    Ok(match self.membership.get(uuid) {
+      Some(groups) => groups.iter().cloned().collect(),
+      None => Vec::new(),
+    })
+  }
 
-    Ok(())
+  /// Calls `callback` with the group and the new state whenever this node
+  /// gains or loses leadership of a contested group. When a leader exits,
+  /// the node rejoins the group so a new election picks a successor.
+  pub fn on_leadership_change<F>(&mut self, callback:F) where F:FnMut(&str, bool) + 'static {
+    self.leadership_callbacks.push(Box::new(callback));
   }
 
-  pub fn pop(&mut self) -> Result<&str> {
-    unsafe {
-      Ok(CStr::from_ptr(zyre_sys::zmsg_popstr(self.sys)).to_str()?)
+  /// Whether the last LEADER event read for `group` named this node. Only
+  /// groups given to `ZyreBuilder::contest` hold elections.
+  pub fn is_leader(&self, group:&str) -> bool {
+    match (self.leaders.get(group), self.uuid()) {
+      (Some(leader), Ok(uuid)) => leader == uuid,
+      _ => false,
     }
   }
 
-  pub fn collect(&mut self) -> Result<Vec<&str>> {
-    let mut frames = Vec::with_capacity(self.size());
+  /// Current peers sorted by name, then UUID, for stable listings. Peers
+  /// whose ENTER event hasn't been read yet are left out.
+  pub fn peers_sorted_by_name(&self) -> Result<Vec<Peer>> {
+    Ok(self.resolve_sorted(self.peers()?))
+  }
 
-    for _ in 0..self.size() {
-      frames.push(unsafe {
-        CStr::from_ptr(zyre_sys::zmsg_popstr(self.sys)).to_str()?
-      });
-    }
+  /// Joins `group`, reads events for `settle` so members can be discovered,
+  /// then returns the group's other members sorted as `peers_sorted_by_name`
+  /// does. The events read are kept for later reads.
+  pub fn join_and_list(&mut self, group:&str, settle:Duration) -> Result<Vec<Peer>> {
+    self.join(group)?;
+
+    let deadline = self.clock.now() + settle;
+    let mut skipped = Vec::new();
+    loop {
+      let now = self.clock.now();
+      if now >= deadline {
+        break;
+      }
+
+      match self.recv_event_timeout(deadline - now) {
+        Ok(Some(event)) => skipped.push(event),
+        Ok(None) => {},
+        Err(err) => {
+          self.pending.extend(skipped);
+          return Err(err);
+        },
+      }
+    }
+    self.pending.extend(skipped);
+
+    Ok(self.resolve_sorted(self.peers_by_group(group)?))
+  }
+
+  fn resolve_sorted(&self, uuids:Vec<String>) -> Vec<Peer> {
+    let mut peers: Vec<Peer> = uuids.iter()
+      .filter_map(|uuid| self.known.get(uuid).cloned())
+      .collect();
+    peers.sort_by(|a, b| (&a.name, &a.uuid).cmp(&(&b.name, &b.uuid)));
+    peers
+  }
+
+  /// Known peers grouped by the subnet of their address, keyed like
+  /// `"192.168.1.0/24"`. `prefix_len` is the mask width in bits; 24 is usual
+  /// for IPv4. Peers whose address can't be parsed are keyed by the address
+  /// itself. Peers whose ENTER event hasn't been read yet are left out.
+  pub fn peers_by_subnet(&self, prefix_len:u8) -> Result<HashMap<String, Vec<Peer>>> {
+    let mut subnets: HashMap<String, Vec<Peer>> = HashMap::new();
+
+    for peer in self.resolve_sorted(self.peers()?) {
+      let key = subnet_of(&peer.address, prefix_len).unwrap_or_else(|| peer.address.clone());
+      subnets.entry(key).or_default().push(peer);
+    }
+
+    Ok(subnets)
+  }
+
+  /// UUIDs of the peers in `group`, not counting this node.
+  pub fn peers_by_group<T>(&self, group:T) -> Result<Vec<String>> where T:AsRef<str> {
+    let peers = unsafe {
+      take_strings(zyre_sys::zyre_peers_by_group(self.sys, CString::new(group.as_ref())?.as_ptr()))?
+    };
+
+    self.compatible(peers)
+  }
+
+  /// Whether this node has joined `group` and no peer is in it, for logic
+  /// that acts alone until others arrive.
+  pub fn is_sole_member(&self, group:&str) -> Result<bool> {
+    Ok(self.own_groups()?.iter().any(|own| own == group) && self.peers_by_group(group)?.is_empty())
+  }
+
+  /// Member counts for every known group. Unlike `peers_by_group`, counts
+  /// include this node in the groups it has joined.
+  pub fn group_summary(&self) -> Result<HashMap<String, usize>> {
+    let own:BTreeSet<String> = self.own_groups()?.into_iter().collect();
+    let mut summary = HashMap::new();
+
+    for group in self.all_groups()? {
+      let mut members = self.peers_by_group(&group)?.len();
+      if own.contains(&group) {
+        members += 1;
+      }
+      summary.insert(group, members);
+    }
+
+    Ok(summary)
+  }
+
+  pub fn read_event(&mut self) -> Result<Event> {
+    match self.pending.pop_front() {
+      Some(event) => Ok(event),
+      None => self.recv_event(),
+    }
+  }
+
+  /// Waits up to `timeout` for an event, returning `None` if none arrived.
+  pub fn read_event_timeout(&mut self, timeout:Duration) -> Result<Option<Event>> {
+    match self.pending.pop_front() {
+      Some(event) => Ok(Some(event)),
+      None => self.recv_event_timeout(timeout),
+    }
+  }
+
+  /// Replies to `event` if it is a ping from `Zyre::ping`, returning whether
+  /// it was one.
+  pub fn answer_ping(&mut self, event:&Event) -> Result<bool> {
+    match pong_for(event) {
+      Some(pong) => {
+        self.whisper(event.peer_uuid()?, pong)?;
+        Ok(true)
+      },
+      None => Ok(false),
+    }
+  }
+
+  pub fn stats(&self) -> Stats {
+    self.stats
+  }
+
+  /// Returns the counters and resets them to zero, for periodic reporting.
+  pub fn take_stats(&mut self) -> Stats {
+    mem::take(&mut self.stats)
+  }
+
+  /// Whispers `reply` back to the sender of a request made with
+  /// `Zyre::request`, tagged so the requester can match it up.
+  pub fn reply_to(&mut self, request:&Event, mut reply:Message) -> Result<()> {
+    let id = correlation(request, REQUEST_FRAME).ok_or(Error::NotARequest)?;
+    reply.push(&id)?;
+    reply.push(REPLY_FRAME)?;
+
+    self.whisper(request.peer_uuid()?, reply)
+  }
+
+  /// Shouts `request` to `group` and collects the replies members send with
+  /// `reply_to` until `timeout` passes or every member has answered. Returns
+  /// each responder's UUID with its reply, in arrival order. Unrelated
+  /// events read while waiting are kept and returned by later reads.
+  pub fn scatter_gather(&mut self, group:&str, mut request:Message, timeout:Duration) -> Result<Vec<(String, Message)>> {
+    let members = self.peers_by_group(group)?.len();
+    let id = wrap_request(&mut request)?;
+    self.shout(group, request)?;
+
+    let deadline = self.clock.now() + timeout;
+    let mut replies = Vec::new();
+    let mut skipped = Vec::new();
+    let result = loop {
+      let now = self.clock.now();
+      if now >= deadline || (members > 0 && replies.len() == members) {
+        break Ok(replies);
+      }
+
+      match self.recv_event_timeout(deadline - now) {
+        Ok(Some(mut event)) => {
+          let is_reply = event.kind().ok() == Some(EventType::Whisper)
+            && correlation(&event, REPLY_FRAME).as_ref() == Some(&id);
+
+          if is_reply {
+            let peer = match event.peer_uuid() {
+              Ok(peer) => peer.to_string(),
+              Err(err) => break Err(err),
+            };
+            replies.push((peer, unwrap_correlated(&mut event)));
+          } else {
+            skipped.push(event);
+          }
+        },
+        Ok(None) => {},
+        Err(err) => break Err(err),
+      }
+    };
+    self.pending.extend(skipped);
+
+    result
+  }
+
+  /// Answers requests shouted to `group` (see `scatter_gather`) as this node
+  /// reads events: `handler` gets the request body, and whatever it returns
+  /// is whispered back as the reply. Returning `None` sends nothing. Either
+  /// way, the request is not returned by reads. Replaces any handler already
+  /// registered for the group.
+  pub fn on_request<F>(&mut self, group:&str, handler:F) where F:FnMut(&Message) -> Option<Message> + Send + 'static {
+    self.request_handlers.insert(group.to_string(), Box::new(handler));
+  }
+
+  fn answer_request(&mut self, event:&mut Event) -> Result<bool> {
+    if event.kind()? != EventType::Shout {
+      return Ok(false);
+    }
+    let id = match correlation(event, REQUEST_FRAME) {
+      Some(id) => id,
+      None => return Ok(false),
+    };
+    let handler = match self.request_handlers.get_mut(event.group()?) {
+      Some(handler) => handler,
+      None => return Ok(false),
+    };
+
+    let body = unwrap_correlated(event);
+    let reply = match catch_handler(|| handler(&body)) {
+      Ok(reply) => reply,
+      Err(message) => {
+        self.record_panic("request handler", message);
+        None
+      },
+    };
+    if let Some(mut reply) = reply {
+      reply.push(&id)?;
+      reply.push(REPLY_FRAME)?;
+      self.whisper(event.peer_uuid()?, reply)?;
+    }
+
+    Ok(true)
+  }
+
+  /// The message of the last panic caught in a handler this node called:
+  /// `run` handlers, `on_request` handlers and `on_leadership_change`
+  /// callbacks. Each is logged (through the `log` crate) and the node carries
+  /// on, so a buggy handler can't silently kill a `ZyreActor`'s thread.
+  pub fn last_panic(&self) -> Option<&str> {
+    self.last_panic.as_deref()
+  }
+
+  fn record_panic(&mut self, context:&str, message:String) {
+    log::error!("zyre {} panicked: {}", context, message);
+    self.last_panic = Some(message);
+  }
+
+  /// Answers pings and header probes automatically whenever this node reads
+  /// events, including from a `ZyreActor`'s thread. Answered probes are not
+  /// returned by reads.
+  pub fn enable_ping_responder(&mut self) {
+    self.ping_responder = true;
+  }
+
+  /// Collapses bursts of shouts to `group`: once a shout arrives, the reader
+  /// keeps reading for `window` and returns only the newest shout to the
+  /// group, whichever peer sent it. Other events read meanwhile are returned
+  /// afterwards, so they may come out of order relative to that shout.
+  pub fn coalesce<T>(&mut self, group:T, window:Duration) where T:AsRef<str> {
+    self.config.coalesce.insert(group.as_ref().to_string(), window);
+  }
+
+  fn recv_event(&mut self) -> Result<Event> {
+    loop {
+      let event = self.recv_raw()?;
+
+      if let Some(event) = self.process(event)? {
+        return self.coalesce_shouts(event);
+      }
+    }
+  }
+
+  /// Destroys every event already queued, without waiting for more, and
+  /// returns how many there were. Bookkeeping such as `groups_of_peer` is
+  /// still updated from them, so use this to skip history at a resync point.
+  pub fn discard_events(&mut self) -> Result<usize> {
+    let mut discarded = self.pending.len();
+    self.pending.clear();
+
+    while self.poll(Duration::from_millis(0))? {
+      let event = self.recv_raw()?;
+      if self.process(event)?.is_some() {
+        discarded += 1;
+      }
+    }
+
+    Ok(discarded)
+  }
+
+  /// Roughly how many events are waiting to be read: those stashed by
+  /// helpers that read ahead, plus one if libzyre has any ready. ZeroMQ
+  /// doesn't report how deep a socket's queue is, so this is a lower bound
+  /// that tells an idle node from a busy one rather than an exact count.
+  pub fn backlog_len(&self) -> Result<usize> {
+    let ready = self.poll(Duration::from_millis(0))?;
+    Ok(self.pending.len() + ready as usize)
+  }
+
+  fn recv_event_timeout(&mut self, timeout:Duration) -> Result<Option<Event>> {
+    let deadline = self.clock.now() + timeout;
+
+    loop {
+      let remaining = deadline.saturating_duration_since(self.clock.now());
+      if self.poll(self.clock.wait_slice(remaining))? {
+        let event = self.recv_raw()?;
+        if let Some(event) = self.process(event)? {
+          return self.coalesce_shouts(event).map(Some);
+        }
+      }
+
+      if self.clock.now() >= deadline {
+        return Ok(None);
+      }
+    }
+  }
+
+  /// Handles anything the crate consumes itself, returning the event only if
+  /// it should reach the caller.
+  fn process(&mut self, mut event:Event) -> Result<Option<Event>> {
+    let size = event.message_ref().map(|message| message.content_size()).unwrap_or(0);
+    let frames = event.message_ref().map(|message| message.size()).unwrap_or(0);
+    self.stats.events += 1;
+    self.stats.bytes += size as u64;
+
+    if self.is_foreign(&event)? {
+      self.stats.foreign += 1;
+      return Ok(None);
+    }
+    self.track_membership(&event)?;
+    self.track_leadership(&event)?;
+    self.track_activity(&event)?;
+
+    if let Some(limit) = self.config.max_inbound_frame {
+      if size > limit {
+        self.stats.rejected += 1;
+        return Ok(None);
+      }
+    }
+
+    if let Some(limit) = self.config.max_frames {
+      if frames > limit {
+        self.stats.rejected += 1;
+        return Ok(None);
+      }
+    }
+
+    if self.ping_responder && (self.answer_ping(&event)? || self.answer_header_probe(&event)?) {
+      return Ok(None);
+    }
+
+    if self.answer_request(&mut event)? {
+      return Ok(None);
+    }
+
+    Ok(Some(event))
+  }
+
+  fn track_membership(&mut self, event:&Event) -> Result<()> {
+    match event.kind()? {
+      EventType::Enter => {
+        let peer = Peer {
+          uuid: event.peer_uuid()?.to_string(),
+          name: event.peer_name()?.into_owned(),
+          address: event.peer_addr()?.to_string(),
+          headers: event.headers()?,
+        };
+        self.known.insert(peer.uuid.clone(), peer);
+      },
+      EventType::Join => {
+        self.membership.entry(event.peer_uuid()?.to_string()).or_default().insert(event.group()?.to_string());
+      },
+      EventType::Leave => {
+        if let Some(groups) = self.membership.get_mut(event.peer_uuid()?) {
+          groups.remove(event.group()?);
+        }
+      },
+      EventType::Exit => {
+        self.membership.remove(event.peer_uuid()?);
+        self.known.remove(event.peer_uuid()?);
+      },
+      _ => {},
+    }
+
+    Ok(())
+  }
+
+  fn track_activity(&mut self, event:&Event) -> Result<()> {
+    match event.kind()? {
+      EventType::Exit => {
+        self.last_seen.remove(event.peer_uuid()?);
+      },
+      // These report that the peer has gone quiet, not that we heard from it.
+      EventType::Evasive | EventType::Silent | EventType::Stop => {},
+      // Event types added to libzyre later may not carry a peer at all.
+      _ => {
+        if let Ok(uuid) = event.peer_uuid() {
+          let now = self.clock.now();
+          self.last_seen.insert(uuid.to_string(), now);
+        }
+      },
+    }
+
+    Ok(())
+  }
+
+  fn track_leadership(&mut self, event:&Event) -> Result<()> {
+    match event.kind()? {
+      EventType::Leader => {
+        let group = event.group()?;
+        let was_leader = self.is_leader(group);
+        self.leaders.insert(group.to_string(), event.peer_uuid()?.to_string());
+
+        let is_leader = self.is_leader(group);
+        if is_leader != was_leader {
+          let mut panics = Vec::new();
+          for callback in self.leadership_callbacks.iter_mut() {
+            if let Err(message) = catch_handler(|| callback(group, is_leader)) {
+              panics.push(message);
+            }
+          }
+          for message in panics {
+            self.record_panic("leadership callback", message);
+          }
+        }
+      },
+      EventType::Exit => {
+        let peer = event.peer_uuid()?;
+        let lost: Vec<String> = self.leaders.iter()
+          .filter(|&(_, leader)| leader == peer)
+          .map(|(group, _)| group.clone())
+          .collect();
+
+        for group in lost {
+          self.leaders.remove(&group);
+
+          // libzyre only holds elections as members join, so rejoin to
+          // start a new one among the survivors.
+          if self.config.contest.contains(&group) && self.groups.contains(&group) {
+            self.leave(&group)?;
+            self.join(&group)?;
+          }
+        }
+      },
+      _ => {},
+    }
+
+    Ok(())
+  }
+
+  /// Whether `event` comes from a peer with a different `app_id`. Only ENTER
+  /// carries headers, so the verdict is remembered until the peer's EXIT.
+  fn is_foreign(&mut self, event:&Event) -> Result<bool> {
+    let app_id = match self.config.app_id {
+      Some(ref app_id) => app_id.clone(),
+      None => return Ok(false),
+    };
+    let peer = match event.peer_uuid() {
+      Ok(peer) => peer.to_string(),
+      // STOP and the like aren't from any peer.
+      Err(Error::MalformedEvent) => return Ok(false),
+      Err(err) => return Err(err),
+    };
+
+    match event.kind()? {
+      EventType::Enter => {
+        if event.header(APP_HEADER)? == Some(app_id.as_str()) {
+          Ok(false)
+        } else {
+          self.foreign.insert(peer);
+          Ok(true)
+        }
+      },
+      EventType::Exit => Ok(self.foreign.remove(&peer)),
+      _ => Ok(self.foreign.contains(&peer)),
+    }
+  }
+
+  fn coalesce_shouts(&mut self, event:Event) -> Result<Event> {
+    let window = match coalesced_group(&event, &self.config.coalesce) {
+      Some((_, window)) => window,
+      None => return Ok(event),
+    };
+    let group = event.group()?.to_string();
+    let deadline = self.clock.now() + window;
+    let mut latest = event;
+
+    loop {
+      let remaining = deadline.saturating_duration_since(self.clock.now());
+      if remaining == Duration::from_millis(0) || !self.poll(self.clock.wait_slice(remaining))? {
+        break;
+      }
+
+      let next = self.recv_raw()?;
+      if let Some(next) = self.process(next)? {
+        let same_group = coalesced_group(&next, &self.config.coalesce).map(|(name, _)| name == group);
+
+        if same_group == Some(true) {
+          self.stats.coalesced += 1;
+          latest = next;
+        } else {
+          self.pending.push_back(next);
+        }
+      }
+    }
+
+    Ok(latest)
+  }
+
+  fn recv_raw(&mut self) -> Result<Event> {
+    if !self.is_started() {
+      return Err(Error::NotStarted);
+    }
+
+    unsafe {
+      let event = zyre_sys::zyre_event_new(self.sys);
+
+      if event.is_null() {
+        Err(Error::ReadInterrupted)
+      } else {
+        let mut event = Event::from_raw(event)?;
+        event.utf8 = self.config.utf8;
+        Ok(event)
+      }
+    }
+  }
+
+  /// Waits up to `timeout` for the node socket to become readable.
+  fn poll(&self, timeout:Duration) -> Result<bool> {
+    if !self.is_started() {
+      return Err(Error::NotStarted);
+    }
+
+    unsafe {
+      let socket = zyre_sys::zyre_socket(self.sys);
+      let mut poller = zyre_sys::zpoller_new(socket as *mut c_void, ptr::null_mut::<c_void>());
+      let ready = zyre_sys::zpoller_wait(poller, timeout_ms(timeout));
+      let terminated = zyre_sys::zpoller_terminated(poller);
+      zyre_sys::zpoller_destroy(&mut poller);
+
+      if !ready.is_null() {
+        Ok(true)
+      } else if terminated {
+        Err(Error::ReadInterrupted)
+      } else {
+        Ok(false)
+      }
+    }
+  }
+
+  /// Measures the round-trip time to `peer`, which must be answering pings
+  /// (see `enable_ping_responder` and `answer_ping`). Events read while waiting for the reply are kept
+  /// and returned by later reads.
+  pub fn ping<T>(&mut self, peer:T, timeout:Duration) -> Result<Duration> where T:AsRef<str> {
+    let peer = peer.as_ref();
+    let stamp = timestamp();
+
+    let mut msg = Message::new();
+    msg.push(&stamp)?;
+    msg.push(PING_FRAME)?;
+
+    let sent = self.clock.now();
+    self.whisper(peer, msg)?;
+
+    let deadline = sent + timeout;
+    let mut skipped = Vec::new();
+    let result = loop {
+      let now = self.clock.now();
+      if now >= deadline {
+        break Err(Error::Timeout);
+      }
+
+      match self.recv_event_timeout(deadline - now) {
+        Ok(Some(event)) => {
+          if is_pong(&event, peer, &stamp) {
+            break Ok(self.clock.now().saturating_duration_since(sent));
+          }
+          skipped.push(event);
+        },
+        Ok(None) => {},
+        Err(err) => break Err(err),
+      }
+    };
+    self.pending.extend(skipped);
+
+    result
+  }
+
+  /// Whether a ping to `peer` is answered within `timeout`. The peer must be
+  /// answering pings, as for `ping`; one that isn't looks unreachable.
+  pub fn can_reach(&mut self, peer_uuid:&str, timeout:Duration) -> Result<bool> {
+    match self.ping(peer_uuid, timeout) {
+      Ok(_) => Ok(true),
+      Err(Error::Timeout) => Ok(false),
+      Err(err) => Err(err),
+    }
+  }
+
+  /// Reads events until a peer is known, for use as a startup barrier with
+  /// gossip discovery. Returns `false` if `timeout` passes first. The events
+  /// read are not lost: later reads return them in order.
+  pub fn wait_for_gossip_ready(&mut self, timeout:Duration) -> Result<bool> {
+    if !self.peers()?.is_empty() {
+      return Ok(true);
+    }
+
+    self.wait_for_enter(timeout, |_| true)
+  }
+
+  /// Reads events until `peer`'s ENTER has been seen. Returns `false` if
+  /// `timeout` passes first. The events read are not lost: later reads
+  /// return them in order.
+  pub fn wait_for_peer(&mut self, peer:&str, timeout:Duration) -> Result<bool> {
+    if self.known.contains_key(peer) {
+      return Ok(true);
+    }
+
+    self.wait_for_enter(timeout, |event| event.peer_uuid().ok() == Some(peer))
+  }
+
+  fn wait_for_enter<F>(&mut self, timeout:Duration, wanted:F) -> Result<bool> where F:Fn(&Event) -> bool {
+    let deadline = self.clock.now() + timeout;
+    let mut skipped = Vec::new();
+    let result = loop {
+      let now = self.clock.now();
+      if now >= deadline {
+        break Ok(false);
+      }
+
+      match self.recv_event_timeout(deadline - now) {
+        Ok(Some(event)) => {
+          let entered = event.kind().ok() == Some(EventType::Enter) && wanted(&event);
+          skipped.push(event);
+          if entered {
+            break Ok(true);
+          }
+        },
+        Ok(None) => {},
+        Err(err) => break Err(err),
+      }
+    };
+    self.pending.extend(skipped);
+
+    result
+  }
+
+  /// Fails with `Error::WhisperToSelf` if `peer` is this node's own UUID.
+  /// An empty message is sent as one zero-length frame; see `shout`.
+  pub fn whisper<T>(&mut self, peer:T, mut msg:Message) -> Result<()> where T:AsRef<str> {
+    if !self.is_started() {
+      return Err(Error::NotStarted);
+    }
+    if peer.as_ref() == self.uuid()? {
+      return Err(Error::WhisperToSelf);
+    }
+    fill_empty(&mut msg);
+
+    unsafe {
+      zyre_sys::zyre_whisper(self.sys, CString::new(peer.as_ref())?.as_ptr(), &mut msg.unwrap());
+    }
+
+    Ok(())
+  }
+
+  /// Whispers a copy of `msg` to every current peer, returning how many
+  /// were sent.
+  pub fn whisper_all_peers(&mut self, msg:Message) -> Result<usize> {
+    let peers = self.peers()?;
+    let mut msg = Some(msg);
+
+    for (index, peer) in peers.iter().enumerate() {
+      // The last peer gets the original instead of a copy.
+      let copy = if index + 1 == peers.len() {
+        msg.take().unwrap()
+      } else {
+        msg.as_ref().unwrap().clone()
+      };
+      self.whisper(peer, copy)?;
+    }
+
+    Ok(peers.len())
+  }
+
+  /// An empty message is sent as one zero-length frame, which is what
+  /// receivers see. libzyre passes the frames to its actor as the tail of a
+  /// multi-part command, so a message with no frames at all would leave that
+  /// command unterminated.
+  pub fn shout<T>(&mut self, group:T, mut msg:Message) -> Result<()> where T:AsRef<str> {
+    check_group(group.as_ref())?;
+    if !self.is_started() {
+      return Err(Error::NotStarted);
+    }
+    if self.muted.contains(group.as_ref()) {
+      self.stats.muted += 1;
+      return Ok(());
+    }
+    fill_empty(&mut msg);
+
+    unsafe {
+      zyre_sys::zyre_shout(self.sys, CString::new(group.as_ref())?.as_ptr(), &mut msg.unwrap());
+    }
+
+    Ok(())
+  }
+
+  /// Makes later shouts to `group` succeed without sending anything, counted
+  /// in `Stats::muted`. The node stays joined and still receives.
+  pub fn mute_group(&mut self, group:&str) {
+    self.muted.insert(group.to_string());
+  }
+
+  pub fn unmute_group(&mut self, group:&str) {
+    self.muted.remove(group);
+  }
+
+  /// Shouts a zero-payload keepalive: a single zero-length frame.
+  pub fn shout_empty(&mut self, group:&str) -> Result<()> {
+    self.shout(group, Message::new())
+  }
+
+  /// Shouts this node's `StatsReport` so monitors can aggregate cluster
+  /// health.
+  pub fn shout_stats<T>(&mut self, group:T) -> Result<()> where T:AsRef<str> {
+    let report = StatsReport {
+      peers: self.peers()?.len(),
+      stats: self.stats,
+    };
+
+    self.shout_command(group, &report)
+  }
+
+  pub fn shout_command<T, C>(&mut self, group:T, command:&C) -> Result<()> where T:AsRef<str>, C:Command {
+    let msg = Message::from_frames(command.to_frames())?;
+    self.shout(group, msg)
+  }
+}
+
+impl Drop for Zyre {
+  fn drop(&mut self) {
+    if !self.config.manual_teardown {
+      self.destroy();
+    }
+  }
+}
+
+/// Whether czmq's `zsys_interrupted` flag is set, as czmq's own signal
+/// handler does on SIGINT or SIGTERM.
+///
+/// The flag is process-wide: while it is set, every node's reads fail with
+/// `Error::ReadInterrupted`.
+pub fn interrupted() -> bool {
+  unsafe {
+    ptr::read_volatile(ptr::addr_of!(zyre_sys::zsys_interrupted)) != 0
+  }
+}
+
+/// Sets or clears czmq's process-wide `zsys_interrupted` flag, so an app's
+/// own Ctrl-C handling can stop every node's reads the way czmq's would.
+pub fn set_interrupted(value:bool) {
+  unsafe {
+    ptr::write_volatile(ptr::addr_of_mut!(zyre_sys::zsys_interrupted), value as c_int);
+  }
+}
+
+/// Each interface czmq can beacon on, by name, with its IPv4 broadcast
+/// address.
+pub(crate) fn broadcast_interfaces() -> Vec<(String, String)> {
+  let mut interfaces = Vec::new();
+
+  unsafe {
+    let mut list = zyre_sys::ziflist_new();
+    let mut name = zyre_sys::ziflist_first(list);
+    while !name.is_null() {
+      let broadcast = zyre_sys::ziflist_broadcast(list);
+      if !broadcast.is_null() {
+        interfaces.push((
+          CStr::from_ptr(name).to_string_lossy().into_owned(),
+          CStr::from_ptr(broadcast).to_string_lossy().into_owned(),
+        ));
+      }
+      name = zyre_sys::ziflist_next(list);
+    }
+    zyre_sys::ziflist_destroy(&mut list);
+  }
+
+  interfaces
+}
+
+fn fill_empty(msg:&mut Message) {
+  if msg.size() == 0 {
+    msg.push_bytes(&[]);
+  }
+}
+
+/// The subnet holding the host of a `tcp://host:port` endpoint, written as
+/// `network/prefix_len`. The prefix is clamped to the address's width.
+fn subnet_of(address:&str, prefix_len:u8) -> Option<String> {
+  let hostport = address.trim_start_matches("tcp://");
+  let host = &hostport[..hostport.rfind(':')?];
+  let host = host.trim_start_matches('[').trim_end_matches(']');
+
+  match host.parse::<IpAddr>().ok()? {
+    IpAddr::V4(ip) => {
+      let prefix = cmp::min(prefix_len, 32) as u32;
+      let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+      Some(format!("{}/{}", Ipv4Addr::from(u32::from(ip) & mask), prefix))
+    },
+    IpAddr::V6(ip) => {
+      let prefix = cmp::min(prefix_len, 128) as u32;
+      let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+      Some(format!("{}/{}", Ipv6Addr::from(u128::from(ip) & mask), prefix))
+    },
+  }
+}
+
+/// libzyre accepts any name and fails only when it can't allocate, so a NULL
+/// node is blamed on the name only when one was given.
+unsafe fn new_sys(config:&Config) -> Result<*mut zyre_t> {
+  let sys = match config.name {
+    Some(ref value) => {
+      let name = CString::new(value.as_str())?;
+      zyre_sys::zyre_new(name.as_ptr())
+    },
+    None => zyre_sys::zyre_new(ptr::null()),
+  };
+
+  match (sys.is_null(), config.name.is_some()) {
+    (false, _) => Ok(sys),
+    (true, true) => Err(Error::InvalidName),
+    (true, false) => Err(Error::AllocationFailed),
+  }
+}
+
+fn timestamp() -> String {
+  let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0));
+  now.as_micros().to_string()
+}
+
+fn whisper_frames(event:&Event, marker:&str) -> Option<Vec<Vec<u8>>> {
+  if event.event_type().ok() != Some("WHISPER") {
+    return None;
+  }
+
+  let frames:Vec<Vec<u8>> = match event.message_ref() {
+    Some(message) => message.frames().into_iter().map(|frame| frame.to_vec()).collect(),
+    None => return None,
+  };
+
+  if frames.first().map(|frame| frame.as_slice()) == Some(marker.as_bytes()) {
+    Some(frames)
+  } else {
+    None
+  }
+}
+
+/// Tags `msg` as a request, returning the id its reply will carry.
+fn wrap_request(msg:&mut Message) -> Result<String> {
+  let id = format!("{}-{}", timestamp(), NEXT_CORRELATION.fetch_add(1, Ordering::SeqCst));
+  msg.push(&id)?;
+  msg.push(REQUEST_FRAME)?;
+
+  Ok(id)
+}
+
+/// The correlation id carried by `event` if its message starts with `marker`.
+fn correlation(event:&Event, marker:&str) -> Option<String> {
+  let message = event.message_ref()?;
+  let frames = message.frames();
+
+  if frames.first() != Some(&marker.as_bytes()) {
+    return None;
+  }
+
+  String::from_utf8(frames.get(1)?.to_vec()).ok()
+}
+
+/// Detaches a correlated message, dropping the marker and id frames.
+fn unwrap_correlated(event:&mut Event) -> Message {
+  let mut msg = event.take_message().unwrap_or_else(Message::new);
+  msg.pop_bytes();
+  msg.pop_bytes();
+  msg
+}
+
+/// The reply owed to `event` if it is a ping.
+fn pong_for(event:&Event) -> Option<Message> {
+  let frames = whisper_frames(event, PING_FRAME)?;
+  let stamp = std::str::from_utf8(frames.get(1)?).ok()?;
+
+  let mut msg = Message::new();
+  msg.push(stamp).ok()?;
+  msg.push(PONG_FRAME).ok()?;
+
+  Some(msg)
+}
+
+fn is_pong(event:&Event, peer:&str, stamp:&str) -> bool {
+  match whisper_frames(event, PONG_FRAME) {
+    Some(frames) => {
+      event.peer_uuid().ok() == Some(peer) && frames.get(1).map(|frame| frame.as_slice()) == Some(stamp.as_bytes())
+    },
+    None => false,
+  }
+}
+
+/// The group and window if `event` is a shout to a coalesced group.
+fn coalesced_group(event:&Event, coalesce:&HashMap<String, Duration>) -> Option<(String, Duration)> {
+  if event.event_type().ok() != Some("SHOUT") {
+    return None;
+  }
+
+  let group = event.group().ok()?;
+  coalesce.get(group).map(|window| (group.to_string(), *window))
+}
+
+fn timeout_ms(timeout:Duration) -> c_int {
+  cmp::min(timeout.as_millis(), c_int::MAX as u128) as c_int
+}
+
+unsafe fn take_strings(mut list:*mut zyre_sys::zlist_t) -> Result<Vec<String>> {
+  let mut strings = Vec::new();
+  if list.is_null() {
+    return Ok(strings);
+  }
+
+  let mut result = Ok(());
+  let mut item = zyre_sys::zlist_first(list);
+  while !item.is_null() {
+    match CStr::from_ptr(item as *const c_char).to_str() {
+      Ok(value) => strings.push(value.to_string()),
+      Err(err) => {
+        result = Err(Error::from(err));
+        break;
+      },
+    }
+    item = zyre_sys::zlist_next(list);
+  }
+  zyre_sys::zlist_destroy(&mut list);
+
+  result.map(|_| strings)
+}
+
+fn env_name(name:&str) -> String {
+  let mut env: String = name.chars()
+    .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+    .collect();
+  if env.is_empty() || env.starts_with(|c:char| c.is_ascii_digit()) {
+    env.insert(0, '_');
+  }
+  env
+}
+
+fn env_value(value:&str) -> String {
+  value.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard base64 with `=` padding, as used by `Zyre::set_header_bytes`.
+fn base64_encode(bytes:&[u8]) -> String {
+  let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+  for chunk in bytes.chunks(3) {
+    let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| group | (byte as u32) << (16 - 8 * i));
+    for i in 0..4 {
+      if i <= chunk.len() {
+        encoded.push(BASE64_ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+      } else {
+        encoded.push('=');
+      }
+    }
+  }
+
+  encoded
+}
+
+fn base64_decode(encoded:&str) -> Result<Vec<u8>> {
+  let encoded = encoded.as_bytes();
+  if !encoded.len().is_multiple_of(4) {
+    return Err(Error::InvalidEncoding);
+  }
+
+  let mut bytes = Vec::with_capacity(encoded.len() / 4 * 3);
+  for (index, chunk) in encoded.chunks(4).enumerate() {
+    let last = index == encoded.len() / 4 - 1;
+    let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+    if padding > 2 || (padding > 0 && !last) {
+      return Err(Error::InvalidEncoding);
+    }
+
+    let mut group = 0u32;
+    for &c in &chunk[..4 - padding] {
+      let value = BASE64_ALPHABET.iter().position(|&a| a == c).ok_or(Error::InvalidEncoding)?;
+      group = group << 6 | value as u32;
+    }
+    group <<= 6 * padding as u32;
+
+    bytes.extend_from_slice(&group.to_be_bytes()[1..4 - padding]);
+  }
+
+  Ok(bytes)
+}
+
+/// The endpoint to bind a gossip hub to and the one to advertise for it,
+/// filling in a wildcard TCP port and host.
+///
+/// libzyre doesn't report the port it binds, so one is picked here by
+/// briefly binding a listener to port 0. Another process could take the
+/// port before libzyre binds it, but only in that short gap.
+fn resolve_gossip_endpoint(endpoint:&str) -> Result<(String, String)> {
+  let hostport = match endpoint.strip_prefix("tcp://") {
+    Some(hostport) => hostport,
+    None => return Ok((endpoint.to_string(), endpoint.to_string())),
+  };
+  let split = match hostport.rfind(':') {
+    Some(split) => split,
+    None => return Ok((endpoint.to_string(), endpoint.to_string())),
+  };
+  let (host, port) = (&hostport[..split], &hostport[split + 1..]);
+
+  let port = if port == "*" || port == "0" {
+    let listen_host = if host == "*" { "0.0.0.0" } else { host.trim_start_matches('[').trim_end_matches(']') };
+    TcpListener::bind((listen_host, 0))?.local_addr()?.port().to_string()
+  } else {
+    port.to_string()
+  };
+
+  let advertised_host = if host == "*" {
+    unsafe { take_string(zyre_sys::zsys_hostname())? }.unwrap_or_else(|| "localhost".to_string())
+  } else {
+    host.to_string()
+  };
+
+  Ok((format!("tcp://{}:{}", host, port), format!("tcp://{}:{}", advertised_host, port)))
+}
+
+/// Runs a user handler, turning a panic into its message.
+fn catch_handler<R, F>(handler:F) -> result::Result<R, String> where F:FnOnce() -> R {
+  panic::catch_unwind(AssertUnwindSafe(handler)).map_err(|payload| {
+    match payload.downcast::<String>() {
+      Ok(message) => *message,
+      Err(payload) => match payload.downcast::<&str>() {
+        Ok(message) => message.to_string(),
+        Err(_) => "non-string panic payload".to_string(),
+      },
+    }
+  })
+}
+
+/// Loads the cert at `path`, or generates and saves one there, and hands it
+/// to libzyre. Returns its public key.
+unsafe fn apply_identity(sys:*mut zyre_t, path:&Path) -> Result<String> {
+  let filename = CString::new(path.to_str().ok_or(Error::IdentityFailed)?)?;
+
+  let mut cert = if path.exists() {
+    zyre_sys::zcert_load(filename.as_ptr())
+  } else {
+    let mut cert = zyre_sys::zcert_new();
+    if !cert.is_null() && zyre_sys::zcert_save(cert, filename.as_ptr()) != 0 {
+      zyre_sys::zcert_destroy(&mut cert);
+      return Err(Error::IdentityFailed);
+    }
+    cert
+  };
+  if cert.is_null() {
+    return Err(Error::IdentityFailed);
+  }
+
+  // libzyre copies the keys out, so the cert stays ours to free.
+  zyre_sys::zyre_set_zcert(sys, cert);
+  let key = CStr::from_ptr(zyre_sys::zcert_public_txt(cert)).to_str().map(String::from);
+  zyre_sys::zcert_destroy(&mut cert);
+
+  Ok(key?)
+}
+
+fn check_group(group:&str) -> Result<()> {
+  if group.len() > MAX_GROUP_LEN {
+    Err(Error::InvalidGroup)
+  } else {
+    Ok(())
+  }
+}
+
+/// Copies and frees a string libzyre handed over.
+unsafe fn take_string(mut ptr:*mut c_char) -> Result<Option<String>> {
+  if ptr.is_null() {
+    return Ok(None);
+  }
+
+  let value = CStr::from_ptr(ptr).to_str().map(String::from);
+  zyre_sys::zstr_free(&mut ptr);
+
+  Ok(Some(value?))
+}
+
+unsafe fn required_str<'a>(ptr:*const c_char) -> Result<&'a str> {
+  optional_str(ptr)?.ok_or(Error::MalformedEvent)
+}
+
+unsafe fn optional_str<'a>(ptr:*const c_char) -> Result<Option<&'a str>> {
+  if ptr.is_null() {
+    Ok(None)
+  } else {
+    Ok(Some(CStr::from_ptr(ptr).to_str()?))
+  }
+}
+
+/// The kinds of event libzyre reports. Types this crate doesn't know about
+/// are kept verbatim in `Other`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EventType {
+  Enter,
+  Exit,
+  Join,
+  Leave,
+  Whisper,
+  Shout,
+  Evasive,
+  Silent,
+  Leader,
+  Stop,
+  Other(String),
+}
+
+impl EventType {
+  pub fn as_str(&self) -> &str {
+    match *self {
+      EventType::Enter => "ENTER",
+      EventType::Exit => "EXIT",
+      EventType::Join => "JOIN",
+      EventType::Leave => "LEAVE",
+      EventType::Whisper => "WHISPER",
+      EventType::Shout => "SHOUT",
+      EventType::Evasive => "EVASIVE",
+      EventType::Silent => "SILENT",
+      EventType::Leader => "LEADER",
+      EventType::Stop => "STOP",
+      EventType::Other(ref other) => other,
+    }
+  }
+}
+
+impl<'a> std::convert::From<&'a str> for EventType {
+  fn from(value:&'a str) -> EventType {
+    match value {
+      "ENTER" => EventType::Enter,
+      "EXIT" => EventType::Exit,
+      "JOIN" => EventType::Join,
+      "LEAVE" => EventType::Leave,
+      "WHISPER" => EventType::Whisper,
+      "SHOUT" => EventType::Shout,
+      "EVASIVE" => EventType::Evasive,
+      "SILENT" => EventType::Silent,
+      "LEADER" => EventType::Leader,
+      "STOP" => EventType::Stop,
+      other => EventType::Other(other.to_string()),
+    }
+  }
+}
+
+impl fmt::Display for EventType {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    write!(formatter, "{}", self.as_str())
+  }
+}
+
+/// How a WHISPER or SHOUT reached this node. `from` is the sender's UUID.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Delivery {
+  Whisper { from: String },
+  Shout { from: String, group: String },
+}
+
+#[derive(Debug)]
+pub struct Event {
+  sys: *mut zyre_sys::zyre_event_t,
+  utf8: Utf8Policy,
+}
+
+impl Event {
+  fn new(event:*mut zyre_sys::zyre_event_t) -> Event {
+    Event {
+      sys: event,
+      utf8: Utf8Policy::default(),
+    }
+  }
+
+  /// Takes ownership of an event from libzyre. Fails with
+  /// `Error::MalformedEvent`, destroying the event, if it is NULL or has no
+  /// type.
+  ///
+  /// # Safety
+  ///
+  /// `event` must be NULL or a valid event that nothing else will destroy.
+  pub unsafe fn from_raw(event:*mut zyre_sys::zyre_event_t) -> Result<Event> {
+    if event.is_null() {
+      return Err(Error::MalformedEvent);
+    }
+
+    let event = Event::new(event);
+    event.event_type()?;
+    Ok(event)
+  }
+
+  pub fn destroy(&mut self) {
+    unsafe {
+      zyre_sys::zyre_event_destroy(&mut self.sys);
+    }
+  }
+
+  pub fn event_type(&self) -> Result<&str> {
+    if self.sys.is_null() {
+      return Err(Error::MalformedEvent);
+    }
+
+    match unsafe { required_str(zyre_sys::zyre_event_type(self.sys))? } {
+      "" => Err(Error::MalformedEvent),
+      kind => Ok(kind),
+    }
+  }
+
+  /// Fails with `Error::UnexpectedFrames` unless the event's message has
+  /// exactly `expected_frames` frames. An event without a message has none.
+  pub fn validate_message(&self, expected_frames:usize) -> Result<()> {
+    let actual = self.message_ref().map(|message| message.size()).unwrap_or(0);
+    if actual == expected_frames {
+      Ok(())
+    } else {
+      Err(Error::UnexpectedFrames { expected: expected_frames, actual })
+    }
+  }
+
+  /// Whether the event is of type `kind`. Compares libzyre's type string in
+  /// place, so unlike `kind` it never allocates; a NULL or malformed event
+  /// is of no type.
+  pub fn is(&self, kind:EventType) -> bool {
+    self.event_type().ok() == Some(kind.as_str())
+  }
+
+  /// The event's type. A type newer than this crate comes back as
+  /// `EventType::Other` holding the raw string rather than an error; only a
+  /// missing or empty type is malformed.
+  pub fn kind(&self) -> Result<EventType> {
+    Ok(EventType::from(self.event_type()?))
+  }
+
+  /// The addressing of a WHISPER or SHOUT; `None` for any other event.
+  pub fn delivery(&self) -> Option<Delivery> {
+    let from = self.peer_uuid().ok()?.to_string();
+
+    match self.kind().ok()? {
+      EventType::Whisper => Some(Delivery::Whisper { from }),
+      EventType::Shout => Some(Delivery::Shout { from, group: self.group().ok()?.to_string() }),
+      _ => None,
+    }
+  }
+
+  pub fn peer_uuid(&self) -> Result<&str> {
+    unsafe {
+      required_str(zyre_sys::zyre_event_peer_uuid(self.sys))
+    }
+  }
+
+  pub fn peer_name(&self) -> Result<Cow<'_, str>> {
+    unsafe {
+      let name = zyre_sys::zyre_event_peer_name(self.sys);
+      if name.is_null() {
+        return Err(Error::MalformedEvent);
+      }
+
+      self.utf8.decode(CStr::from_ptr(name).to_bytes())
+    }
+  }
+
+  pub fn peer_addr(&self) -> Result<&str> {
+    unsafe {
+      required_str(zyre_sys::zyre_event_peer_addr(self.sys))
+    }
+  }
+
+  pub fn group(&self) -> Result<&str> {
+    unsafe {
+      required_str(zyre_sys::zyre_event_group(self.sys))
+    }
+  }
+
+  /// The value of a header the peer advertised. Only ENTER events carry
+  /// headers.
+  pub fn header<T>(&self, name:T) -> Result<Option<&str>> where T:AsRef<str> {
+    unsafe {
+      optional_str(zyre_sys::zyre_event_header(self.sys, CString::new(name.as_ref())?.as_ptr()))
+    }
+  }
+
+  /// A header set with `Zyre::set_header_bytes`, decoded from base64. Fails
+  /// with `Error::InvalidEncoding` if the value isn't base64.
+  pub fn header_bytes<T>(&self, name:T) -> Result<Option<Vec<u8>>> where T:AsRef<str> {
+    match self.header(name)? {
+      Some(value) => Ok(Some(base64_decode(value)?)),
+      None => Ok(None),
+    }
+  }
+
+  /// The peer's `ZyreBuilder::display_name`, falling back to its node name.
+  pub fn display_name(&self) -> Result<Cow<'_, str>> {
+    match self.header(DISPLAY_NAME_HEADER)? {
+      Some(name) => Ok(Cow::Borrowed(name)),
+      None => self.peer_name(),
+    }
+  }
+
+  pub fn headers(&self) -> Result<HashMap<String, String>> {
+    let mut headers = HashMap::new();
+
+    unsafe {
+      let hash = zyre_sys::zyre_event_headers(self.sys);
+      if hash.is_null() {
+        return Ok(headers);
+      }
+
+      let mut value = zyre_sys::zhash_first(hash);
+      while !value.is_null() {
+        let key = CStr::from_ptr(zyre_sys::zhash_cursor(hash)).to_str()?;
+        let value_str = CStr::from_ptr(value as *const c_char).to_str()?;
+        headers.insert(key.to_string(), value_str.to_string());
+        value = zyre_sys::zhash_next(hash);
+      }
+    }
+
+    Ok(headers)
+  }
+
+  /// The headers as `KEY=VALUE` lines, sorted, for handing to tools that
+  /// read environment-style input.
+  ///
+  /// Names are upper-cased with anything but ASCII letters, digits and `_`
+  /// replaced by `_` (`X-DISPLAY-NAME` becomes `X_DISPLAY_NAME`). Backslashes
+  /// and newlines in values are escaped as `\\` and `\n` so each header
+  /// stays on one line.
+  pub fn headers_as_env(&self) -> Result<Vec<String>> {
+    let mut lines: Vec<String> = self.headers()?.iter()
+      .map(|(name, value)| format!("{}={}", env_name(name), env_value(value)))
+      .collect();
+    lines.sort();
+
+    Ok(lines)
+  }
+
+  /// Copies everything about the event into an `OwnedEvent`, which can be
+  /// cloned and sent between threads.
+  pub fn snapshot(&self) -> Result<OwnedEvent> {
+    unsafe {
+      Ok(OwnedEvent {
+        event_type: self.event_type()?.to_string(),
+        peer_uuid: self.peer_uuid()?.to_string(),
+        peer_name: self.peer_name()?.to_string(),
+        peer_addr: optional_str(zyre_sys::zyre_event_peer_addr(self.sys))?.map(String::from),
+        headers: self.headers()?,
+        group: optional_str(zyre_sys::zyre_event_group(self.sys))?.map(String::from),
+        frames: self.raw_message_frames(),
+      })
+    }
+  }
+
+  /// Decodes the event's message as a `C`, without detaching it.
+  pub fn command<C>(&self) -> Result<C> where C:Command {
+    let message = self.message_ref().ok_or(Error::InvalidCommand)?;
+    let frames = message.collect()?;
+    let frames: Vec<&str> = frames.iter().map(|frame| frame.as_ref()).collect();
+
+    C::from_frames(&frames).ok_or(Error::InvalidCommand)
+  }
+
+  #[deprecated(note = "use `take_message` or `message_ref`")]
+  pub fn message(&mut self) -> Message {
+    self.take_message().unwrap_or_else(Message::new)
+  }
+
+  /// Borrows the event's message without detaching it. The returned value
+  /// cannot outlive the event and never frees the underlying zmsg.
+  pub fn message_ref(&self) -> Option<MessageRef<'_>> {
+    unsafe {
+      let msg = zyre_sys::zyre_event_msg(self.sys);
+
+      if msg.is_null() {
+        None
+      } else {
+        Some(MessageRef { sys: msg, utf8: self.utf8, event: PhantomData })
+      }
+    }
+  }
+
+  /// Copies the message's frames as raw bytes, in wire order, without
+  /// detaching it or assuming UTF-8. Empty if there is no message.
+  pub fn raw_message_frames(&self) -> Vec<Vec<u8>> {
+    match self.message_ref() {
+      Some(message) => message.frames().into_iter().map(|frame| frame.to_vec()).collect(),
+      None => Vec::new(),
+    }
+  }
+
+  /// Detaches the message from the event, transferring ownership to the
+  /// caller. Later calls (and `message_ref`) return `None`.
+  pub fn take_message(&mut self) -> Option<Message> {
+    unsafe {
+      let msg = zyre_sys::zyre_event_get_msg(self.sys);
+
+      if msg.is_null() {
+        None
+      } else {
+        let mut msg = Message::from_ptr(msg);
+        msg.utf8 = self.utf8;
+        Some(msg)
+      }
+    }
+  }
+}
+
+impl Drop for Event {
+  fn drop(&mut self) {
+    self.destroy();
+  }
+}
+
+/// A peer as it introduced itself in its ENTER event.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Peer {
+  pub uuid: String,
+  pub name: String,
+  pub address: String,
+  pub headers: HashMap<String, String>,
+}
+
+/// A self-contained copy of an `Event`. Message frames are kept in wire
+/// order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnedEvent {
+  pub event_type: String,
+  pub peer_uuid: String,
+  pub peer_name: String,
+  pub peer_addr: Option<String>,
+  pub headers: HashMap<String, String>,
+  pub group: Option<String>,
+  pub frames: Vec<Vec<u8>>,
+}
+
+impl OwnedEvent {
+  pub fn kind(&self) -> EventType {
+    EventType::from(self.event_type.as_str())
+  }
+
+  /// Serializes the event for forwarding to another process.
+  ///
+  /// Every field is a 4-byte big-endian length followed by that many bytes.
+  /// The layout is: type, peer UUID, peer name, a presence byte and field
+  /// for each of address and group, a 4-byte header count followed by
+  /// name/value fields (sorted by name), and a 4-byte frame count followed
+  /// by the frames in wire order.
+  pub fn encode(&self) -> Vec<u8> {
+    let mut bytes = Vec::new();
+
+    encode_field(&mut bytes, self.event_type.as_bytes());
+    encode_field(&mut bytes, self.peer_uuid.as_bytes());
+    encode_field(&mut bytes, self.peer_name.as_bytes());
+
+    for optional in &[&self.peer_addr, &self.group] {
+      match **optional {
+        Some(ref value) => {
+          bytes.push(1);
+          encode_field(&mut bytes, value.as_bytes());
+        },
+        None => bytes.push(0),
+      }
+    }
+
+    let mut headers: Vec<_> = self.headers.iter().collect();
+    headers.sort();
+    bytes.extend_from_slice(&(headers.len() as u32).to_be_bytes());
+    for (name, value) in headers {
+      encode_field(&mut bytes, name.as_bytes());
+      encode_field(&mut bytes, value.as_bytes());
+    }
+
+    bytes.extend_from_slice(&(self.frames.len() as u32).to_be_bytes());
+    for frame in &self.frames {
+      encode_field(&mut bytes, frame);
+    }
+
+    bytes
+  }
+
+  /// Parses bytes produced by `encode`. Fails with `Error::InvalidEncoding`
+  /// if they are truncated, have trailing data, or hold invalid UTF-8.
+  pub fn decode(bytes:&[u8]) -> Result<OwnedEvent> {
+    let mut decoder = Decoder { bytes };
+
+    let event_type = decoder.string()?;
+    let peer_uuid = decoder.string()?;
+    let peer_name = decoder.string()?;
+    let peer_addr = decoder.optional_string()?;
+    let group = decoder.optional_string()?;
+
+    let mut headers = HashMap::new();
+    for _ in 0..decoder.count()? {
+      let name = decoder.string()?;
+      headers.insert(name, decoder.string()?);
+    }
+
+    let mut frames = Vec::new();
+    for _ in 0..decoder.count()? {
+      frames.push(decoder.field()?.to_vec());
+    }
+
+    if !decoder.bytes.is_empty() {
+      return Err(Error::InvalidEncoding);
+    }
+
+    Ok(OwnedEvent { event_type, peer_uuid, peer_name, peer_addr, headers, group, frames })
+  }
+}
+
+fn encode_field(bytes:&mut Vec<u8>, field:&[u8]) {
+  bytes.extend_from_slice(&(field.len() as u32).to_be_bytes());
+  bytes.extend_from_slice(field);
+}
+
+/// Reads fields written by `OwnedEvent::encode` off the front of `bytes`.
+struct Decoder<'a> {
+  bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+  fn take(&mut self, len:usize) -> Result<&'a [u8]> {
+    if self.bytes.len() < len {
+      return Err(Error::InvalidEncoding);
+    }
+
+    let (head, tail) = self.bytes.split_at(len);
+    self.bytes = tail;
+    Ok(head)
+  }
+
+  fn count(&mut self) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(self.take(4)?);
+    Ok(u32::from_be_bytes(buf))
+  }
+
+  fn field(&mut self) -> Result<&'a [u8]> {
+    let len = self.count()? as usize;
+    self.take(len)
+  }
+
+  fn string(&mut self) -> Result<String> {
+    String::from_utf8(self.field()?.to_vec()).map_err(|_| Error::InvalidEncoding)
+  }
+
+  fn optional_string(&mut self) -> Result<Option<String>> {
+    match self.take(1)?[0] {
+      0 => Ok(None),
+      1 => self.string().map(Some),
+      _ => Err(Error::InvalidEncoding),
+    }
+  }
+}
+
+#[derive(Debug)]
+pub struct Message {
+  sys: *mut zmsg_t,
+  utf8: Utf8Policy,
+}
+
+impl Message {
+  pub fn new() -> Message {
+    Message {
+      sys:unsafe { zyre_sys::zmsg_new() },
+      utf8: Utf8Policy::default(),
+    }
+  }
+
+  pub fn from_frames<T>(frames:Vec<T>) -> Result<Message>
+  where T:AsRef<str> {
+    let mut msg = Message::new();
+
+    for frame in frames {
+      msg.push(frame)?;
+    }
+
+    Ok(msg)
+  }
+
+  fn from_ptr(sys:*mut zmsg_t) -> Message {
+    Message {
+      sys:sys,
+      utf8: Utf8Policy::default(),
+    }
+  }
+
+  pub fn destroy(&mut self) {
+    unsafe {
+      zyre_sys::zmsg_destroy(&mut self.sys);
+    }
+  }
+
+  fn unwrap(&mut self) -> *mut zmsg_t {
+    let temp = self.sys;
+    self.sys = 0 as *mut _;
+    temp
+  }
+
+  pub fn size(&self) -> usize {
+    unsafe {
+      zyre_sys::zmsg_size(self.sys)
+    }
+  }
+
+  /// Whether the number of frames falls in `frame_counts`, for checking a
+  /// peer's message has the expected shape before taking it apart.
+  pub fn matches_schema(&self, frame_counts:Range<usize>) -> bool {
+    frame_counts.contains(&self.len())
+  }
+
+  /// Number of frames; the same as `size`.
+  pub fn len(&self) -> usize {
+    self.size()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.size() == 0
+  }
+
+  /// Total bytes across all frames.
+  pub fn content_size(&self) -> usize {
+    unsafe {
+      zyre_sys::zmsg_content_size(self.sys)
+    }
+  }
+
+  pub fn push<T>(&mut self, frame:T) -> Result<()> where T:AsRef<str> {
+    unsafe {
+      zyre_sys::zmsg_pushstr(self.sys, CString::new(frame.as_ref())?.as_ptr());
+    }
+
+    Ok(())
+  }
+
+  /// Messages taken from an event inherit the node's policy; others are
+  /// `Strict` until changed here.
+  pub fn set_utf8_policy(&mut self, policy:Utf8Policy) {
+    self.utf8 = policy;
+  }
+
+  /// Removes the first frame as a string. Fails with `Error::InvalidFrame`
+  /// if the message is empty.
+  pub fn pop(&mut self) -> Result<String> {
+    let frame = self.pop_bytes().ok_or(Error::InvalidFrame)?;
+    Ok(self.utf8.decode(&frame)?.into_owned())
+  }
+
+  pub fn push_bytes(&mut self, frame:&[u8]) {
+    unsafe {
+      zyre_sys::zmsg_pushmem(self.sys, frame.as_ptr() as *const c_void, frame.len());
+    }
+  }
+
+  /// Removes the first frame, or returns `None` if the message is empty.
+  pub fn pop_bytes(&mut self) -> Option<Vec<u8>> {
+    unsafe {
+      let mut frame = zyre_sys::zmsg_pop(self.sys);
+      if frame.is_null() {
+        return None;
+      }
+
+      let size = zyre_sys::zframe_size(frame);
+      let bytes = if size == 0 {
+        Vec::new()
+      } else {
+        slice::from_raw_parts(zyre_sys::zframe_data(frame), size).to_vec()
+      };
+      zyre_sys::zframe_destroy(&mut frame);
+
+      Some(bytes)
+    }
+  }
+
+  pub fn push_u32_be(&mut self, value:u32) {
+    self.push_bytes(&value.to_be_bytes());
+  }
+
+  pub fn push_u64_be(&mut self, value:u64) {
+    self.push_bytes(&value.to_be_bytes());
+  }
+
+  /// Pops a 4-byte big-endian frame. Fails with `Error::InvalidFrame` if the
+  /// message is empty or the frame is any other size.
+  pub fn pop_u32_be(&mut self) -> Result<u32> {
+    let mut bytes = [0; 4];
+    self.pop_exact(&mut bytes)?;
+    Ok(u32::from_be_bytes(bytes))
+  }
+
+  /// Pops an 8-byte big-endian frame. Fails with `Error::InvalidFrame` if the
+  /// message is empty or the frame is any other size.
+  pub fn pop_u64_be(&mut self) -> Result<u64> {
+    let mut bytes = [0; 8];
+    self.pop_exact(&mut bytes)?;
+    Ok(u64::from_be_bytes(bytes))
+  }
+
+  fn pop_exact(&mut self, bytes:&mut [u8]) -> Result<()> {
+    match self.pop_bytes() {
+      Some(ref frame) if frame.len() == bytes.len() => {
+        bytes.copy_from_slice(frame);
+        Ok(())
+      },
+      _ => Err(Error::InvalidFrame),
+    }
+  }
+
+  /// Panics unless the frames, in wire order, equal `expected`. The message
+  /// names the first frame that differs and both lengths, for test failures
+  /// that are quick to read.
+  pub fn assert_frames(&self, expected:&[&[u8]]) {
+    let actual = unsafe { frames_of(self.sys) };
+
+    for index in 0..cmp::max(actual.len(), expected.len()) {
+      match (actual.get(index), expected.get(index)) {
+        (Some(actual), Some(expected)) if actual == expected => {},
+        (Some(actual), Some(expected)) => panic!(
+          "frame {} differs: expected {} bytes {:?}, got {} bytes {:?}",
+          index, expected.len(), expected, actual.len(), actual
+        ),
+        (None, Some(expected)) => panic!(
+          "frame {} missing: expected {} bytes {:?}, message has {} frames",
+          index, expected.len(), expected, actual.len()
+        ),
+        (Some(actual), None) => panic!(
+          "frame {} unexpected: got {} bytes {:?}, expected {} frames",
+          index, actual.len(), actual, expected.len()
+        ),
+        (None, None) => unreachable!(),
+      }
+    }
+  }
+
+  /// Like `collect`, but also returns how many frames the message held
+  /// beforehand. Fewer strings than that means frames were lost along the
+  /// way, which is worth logging.
+  pub fn collect_counted(&mut self) -> Result<(Vec<String>, usize)> {
+    let count = self.len();
+    Ok((self.collect()?, count))
+  }
+
+  pub fn collect(&mut self) -> Result<Vec<String>> {
+    let mut frames = Vec::with_capacity(self.size());
+
+    for _ in 0..self.size() {
+      frames.push(self.pop()?);
+    }
+    frames.reverse();
+
+    Ok(frames)
+  }
+
+  /// Consumes the message, returning its frames in the order `collect`
+  /// does. With `lossy`, invalid UTF-8 is replaced with U+FFFD whatever the
+  /// message's own policy; otherwise it fails with `Error::FromCStr`. The
+  /// underlying zmsg is freed either way.
+  pub fn into_strings(mut self, lossy:bool) -> Result<Vec<String>> {
+    let policy = if lossy { Utf8Policy::Lossy } else { Utf8Policy::Strict };
+    let mut frames = Vec::with_capacity(self.size());
+
+    while let Some(frame) = self.pop_bytes() {
+      frames.push(policy.decode(&frame)?.into_owned());
+    }
+    frames.reverse();
+
+    Ok(frames)
+  }
+}
+
+impl fmt::Display for Message {
+  /// A one-line summary, such as `Message(3 frames, 128 bytes)`.
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    write!(formatter, "Message({} frames, {} bytes)", self.len(), self.content_size())
+  }
+}
+
+impl Clone for Message {
+  /// Deep-copies every frame.
+  fn clone(&self) -> Message {
+    Message {
+      sys: unsafe { zyre_sys::zmsg_dup(self.sys) },
+      utf8: self.utf8,
+    }
+  }
+}
+
+impl Drop for Message {
+  fn drop(&mut self) {
+    self.destroy();
+  }
+}
+
+// A zmsg has a single owner and no thread affinity, so handing it to another
+// thread (e.g. the actor's) is sound.
+unsafe impl Send for Message {}
+
+/// A read-only view of a message still owned by its `Event`.
+///
+/// ```compile_fail
+/// # use zyre::{ Event, MessageRef };
+/// fn outlive(event: Event) -> MessageRef<'static> {
+///   event.message_ref().unwrap()
+/// }
+/// ```
+#[derive(Debug)]
+pub struct MessageRef<'a> {
+  sys: *mut zmsg_t,
+  utf8: Utf8Policy,
+  event: PhantomData<&'a Event>,
+}
+
+impl<'a> MessageRef<'a> {
+  pub fn size(&self) -> usize {
+    unsafe {
+      zyre_sys::zmsg_size(self.sys)
+    }
+  }
+
+  /// Total bytes across all frames.
+  pub fn content_size(&self) -> usize {
+    unsafe {
+      zyre_sys::zmsg_content_size(self.sys)
+    }
+  }
+
+  /// Raw frame contents, in the order they appear on the wire.
+  pub fn frames(&self) -> Vec<&'a [u8]> {
+    unsafe {
+      frames_of(self.sys)
+    }
+  }
+
+  /// String frames, in the same order `Message::collect` returns them.
+  pub fn collect(&self) -> Result<Vec<Cow<'a, str>>> {
+    let mut frames = Vec::with_capacity(self.size());
+
+    for frame in self.frames() {
+      frames.push(self.utf8.decode(frame)?);
+    }
     frames.reverse();
 
-    Ok(frames)
+    Ok(frames)
+  }
+}
+
+unsafe fn frames_of<'a>(msg:*mut zmsg_t) -> Vec<&'a [u8]> {
+  let mut frames = Vec::new();
+  let mut frame = zyre_sys::zmsg_first(msg);
+
+  while !frame.is_null() {
+    let size = zyre_sys::zframe_size(frame);
+
+    frames.push(if size == 0 {
+      &[][..]
+    } else {
+      slice::from_raw_parts(zyre_sys::zframe_data(frame), size)
+    });
+    frame = zyre_sys::zmsg_next(msg);
+  }
+
+  frames
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::atomic::{ AtomicUsize, Ordering };
+
+  static NEXT_PORT: AtomicUsize = AtomicUsize::new(5800);
+
+  /// A beacon port no other test uses, so nodes from parallel tests don't
+  /// discover each other.
+  pub fn unique_port() -> u16 {
+    NEXT_PORT.fetch_add(1, Ordering::SeqCst) as u16
+  }
+
+  #[test]
+  fn new_destroy() {
+    let mut zyre = Zyre::new(None).ok().unwrap();
+    zyre.destroy();
+  }
+
+  #[test]
+  fn double_destroy() {
+    let mut zyre = Zyre::new(None).ok().unwrap();
+    zyre.destroy();
+    zyre.destroy();
+  }
+
+  fn acquire_context<F>(test_fn:F) where F:Fn(&mut Zyre) {
+    let mut zyre = Zyre::new(Some("test")).ok().unwrap();
+
+    test_fn(&mut zyre);
+
+    zyre.destroy();
+  }
+
+  #[test]
+  fn uuid_length() {
+    acquire_context(|zyre:&mut Zyre| {
+      assert_eq!(zyre.uuid().unwrap().len(), 32);
+    });
+  }
+
+  #[test]
+  fn name_value() {
+    acquire_context(|zyre:&mut Zyre| {
+      assert_eq!(zyre.name().unwrap(), "test");
+    });
+  }
+
+  #[test]
+  fn start_stop() {
+    acquire_context(|zyre:&mut Zyre| {
+      zyre.start().ok();
+      zyre.stop();
+    });
+  }
+
+  #[test]
+  fn manual_teardown() {
+    let mut zyre = ZyreBuilder::new().name("manual").port(unique_port()).manual_teardown().build().unwrap();
+    zyre.start().unwrap();
+    let mut raw = zyre.into_raw();
+
+    unsafe {
+      zyre_sys::zyre_stop(raw);
+      zyre_sys::zyre_destroy(&mut raw);
+    }
+    assert!(raw.is_null());
+
+    // Dropping leaves the node alone, so it can still be used afterwards.
+    let zyre = ZyreBuilder::new().name("borrowed").manual_teardown().build().unwrap();
+    let mut raw = zyre.as_raw();
+    drop(zyre);
+
+    unsafe {
+      assert!(!zyre_sys::zyre_uuid(raw).is_null());
+      zyre_sys::zyre_destroy(&mut raw);
+    }
+  }
+
+  #[test]
+  fn uptime() {
+    acquire_context(|zyre:&mut Zyre| {
+      assert!(zyre.uptime().is_none());
+      zyre.start().unwrap();
+      std::thread::sleep(Duration::from_millis(50));
+      assert!(zyre.uptime().unwrap() >= Duration::from_millis(50));
+      zyre.stop();
+      assert!(zyre.uptime().is_none());
+    });
+  }
+
+  #[test]
+  fn timeouts() {
+    let zyre = Zyre::new(Some("defaults")).unwrap();
+    assert_eq!(zyre.timeouts(), Timeouts::default());
+
+    let zyre = ZyreBuilder::new()
+      .evasive_timeout(Duration::from_millis(1500))
+      .silent_timeout(Duration::from_secs(3))
+      .expired_timeout(Duration::from_secs(10))
+      .build().unwrap();
+    assert_eq!(zyre.timeouts(), Timeouts {
+      evasive: Duration::from_millis(1500),
+      silent: Duration::from_secs(3),
+      expired: Duration::from_secs(10),
+    });
+  }
+
+  #[test]
+  fn new_invalid_name() {
+    match Zyre::new(Some("bad\0name")) {
+      Err(Error::ToCString(_)) => {},
+      other => panic!("expected ToCString, got {:?}", other.map(|_| ())),
+    }
+    match ZyreBuilder::new().name("also\0bad").build() {
+      Err(Error::ToCString(_)) => {},
+      other => panic!("expected ToCString, got {:?}", other.map(|_| ())),
+    }
+
+    // Allocation failure can't be provoked, but it must stay distinct.
+    let err: io::Error = Error::InvalidName.into();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    let err: io::Error = Error::AllocationFailed.into();
+    assert_eq!(err.kind(), io::ErrorKind::Other);
+    assert!(Zyre::new(None).is_ok());
+  }
+
+  #[test]
+  fn effective_interval() {
+    let zyre = Zyre::new(Some("default")).unwrap();
+    assert_eq!(zyre.effective_interval(), Duration::from_secs(1));
+
+    let mut zyre = ZyreBuilder::new().name("fast").port(unique_port()).interval(Duration::from_millis(250)).build().unwrap();
+    zyre.start().unwrap();
+    assert_eq!(zyre.effective_interval(), Duration::from_millis(250));
+  }
+
+  #[test]
+  fn switch_discovery() {
+    let id = unique_port();
+    let hub_endpoint = format!("inproc://switch-hub-{}", id);
+    let mut hub = ZyreBuilder::new().name("hub").endpoint(format!("inproc://hub-{}", id)).gossip_bind(&hub_endpoint).build_started().unwrap();
+    let mut node = ZyreBuilder::new().name("node").port(unique_port()).join("KEPT").build_started().unwrap();
+
+    node.switch_discovery(DiscoveryMode::GossipClient {
+      endpoint: format!("inproc://node-{}", id),
+      hub: hub_endpoint,
+    }).unwrap();
+    assert!(node.wait_for_gossip_ready(Duration::from_secs(5)).unwrap());
+
+    let node_uuid = node.uuid().unwrap().to_string();
+    let join = read_until(&mut hub, |event| event.kind().unwrap() == EventType::Join);
+    assert_eq!(join.peer_uuid().unwrap(), node_uuid);
+    assert_eq!(join.group().unwrap(), "KEPT");
+  }
+
+  #[test]
+  fn gossip_endpoint() {
+    let id = unique_port();
+    let hub = ZyreBuilder::new().name("hub").endpoint(format!("inproc://wild-hub-{}", id)).gossip_bind("tcp://127.0.0.1:*").build_started().unwrap();
+    let endpoint = hub.gossip_endpoint().unwrap();
+    let port:u16 = endpoint.trim_start_matches("tcp://127.0.0.1:").parse().unwrap();
+    assert!(port > 0);
+
+    let mut client = ZyreBuilder::new().name("client").endpoint(format!("inproc://wild-client-{}", id)).gossip_connect(&endpoint).build_started().unwrap();
+    assert!(client.wait_for_gossip_ready(Duration::from_secs(5)).unwrap());
+    assert_eq!(client.gossip_endpoint(), None);
+  }
+
+  #[test]
+  fn resolve_gossip_endpoint() {
+    let (bind, advertised) = super::resolve_gossip_endpoint("tcp://*:*").unwrap();
+    assert!(bind.starts_with("tcp://*:"));
+    assert!(!advertised.contains('*'));
+    assert_eq!(bind.rsplit(':').next(), advertised.rsplit(':').next());
+
+    let fixed = "tcp://10.0.0.1:5670".to_string();
+    assert_eq!(super::resolve_gossip_endpoint(&fixed).unwrap(), (fixed.clone(), fixed));
+    let inproc = "inproc://hub".to_string();
+    assert_eq!(super::resolve_gossip_endpoint(&inproc).unwrap(), (inproc.clone(), inproc));
+  }
+
+  #[test]
+  fn restart() {
+    acquire_context(|zyre:&mut Zyre| {
+      zyre.start().unwrap();
+      zyre.join("RESTART").unwrap();
+      let before = zyre.uuid().unwrap().to_string();
+      assert!(zyre.previous_uuid().is_none());
+
+      zyre.restart().unwrap();
+      assert!(zyre.is_started());
+      assert_eq!(zyre.previous_uuid(), Some(before.as_str()));
+      assert_ne!(zyre.uuid().unwrap(), before);
+      assert_eq!(zyre.own_groups().unwrap(), vec!["RESTART"]);
+    });
+  }
+
+  #[test]
+  fn healthy_after_start() {
+    acquire_context(|zyre:&mut Zyre| {
+      assert!(!zyre.is_healthy());
+      zyre.start().unwrap();
+      assert!(zyre.is_healthy());
+      zyre.stop();
+      assert!(!zyre.is_healthy());
+    });
+  }
+
+  fn acquire_started_context<F>(test_fn:F) where F:Fn(&mut Zyre) {
+    acquire_context(|zyre:&mut Zyre| {
+      zyre.start().ok();
+
+      test_fn(zyre);
+
+      zyre.stop();
+    });
+  }
+
+  #[test]
+  fn join_leave() {
+    acquire_started_context(|zyre:&mut Zyre| {
+      zyre.join("GLOBAL").ok();
+      zyre.leave("GLOBAL").ok();
+    });
+  }
+
+  #[test]
+  fn join_all_partial() {
+    acquire_started_context(|zyre:&mut Zyre| {
+      let (joined, failed) = zyre.join_all(vec!["ALPHA", "BAD\0GROUP", "BETA"]);
+      assert_eq!(joined, vec!["ALPHA", "BETA"]);
+      assert_eq!(failed.len(), 1);
+      assert_eq!(failed[0].0, "BAD\0GROUP");
+
+      let mut groups = zyre.own_groups().unwrap();
+      groups.sort();
+      assert_eq!(groups, vec!["ALPHA", "BETA"]);
+    });
+  }
+
+  #[test]
+  fn read_event() {
+    acquire_started_context(|zyre:&mut Zyre| {
+      zyre.read_event().ok();
+    });
+  }
+
+  #[test]
+  fn event_read_destroy() {
+    acquire_started_context(|zyre:&mut Zyre| {
+      let mut event = zyre.read_event().unwrap();
+      event.destroy();
+    });
+  }
+
+  #[test]
+  fn event_from_null_raw() {
+    match unsafe { Event::from_raw(ptr::null_mut()) } {
+      Err(Error::MalformedEvent) => {},
+      other => panic!("expected MalformedEvent, got {:?}", other),
+    }
+  }
+
+  // libzyre builds events only from its own actor pipe, so there's no way to
+  // hand `from_raw` a novel type; this covers everything `kind` does with one.
+  #[test]
+  fn event_type_unknown() {
+    let kind = EventType::from("NEWTYPE");
+    assert_eq!(kind, EventType::Other("NEWTYPE".to_string()));
+    assert_eq!(kind.as_str(), "NEWTYPE");
+    assert_eq!(kind.to_string(), "NEWTYPE");
+    assert_eq!(EventType::from(kind.as_str()), kind);
+
+    let owned = OwnedEvent {
+      event_type: "NEWTYPE".to_string(),
+      peer_uuid: String::new(),
+      peer_name: String::new(),
+      peer_addr: None,
+      headers: HashMap::new(),
+      group: None,
+      frames: Vec::new(),
+    };
+    match owned.kind() {
+      EventType::Other(ref raw) if raw == "NEWTYPE" => {},
+      other => panic!("expected Other(\"NEWTYPE\"), got {:?}", other),
+    }
+    assert_eq!(OwnedEvent::decode(&owned.encode()).unwrap().kind(), kind);
+  }
+
+  #[test]
+  fn event_is() {
+    let port = unique_port();
+    let mut observer = ZyreBuilder::new().name("observer").port(port).build_started().unwrap();
+    let _peer = ZyreBuilder::new().name("peer").port(port).build_started().unwrap();
+
+    let enter = observer.read_event().unwrap();
+    assert!(enter.is(EventType::Enter));
+    assert!(!enter.is(EventType::Exit));
+    assert!(!enter.is(EventType::Other("enter".to_string())));
+
+    let null = Event { sys: ptr::null_mut(), utf8: Utf8Policy::default() };
+    assert!(!null.is(EventType::Enter));
+  }
+
+  #[test]
+  fn event_double_destroy() {
+    acquire_started_context(|zyre:&mut Zyre| {
+      let mut event = zyre.read_event().unwrap();
+      event.destroy();
+      event.destroy();
+    });
+  }
+
+  #[test]
+  fn shout_before_start() {
+    acquire_context(|zyre:&mut Zyre| {
+      match zyre.shout("GLOBAL", Message::new()) {
+        Err(Error::NotStarted) => {},
+        other => panic!("expected NotStarted, got {:?}", other),
+      }
+    });
+  }
+
+  #[test]
+  fn whisper_to_self() {
+    acquire_started_context(|zyre:&mut Zyre| {
+      let uuid = zyre.uuid().unwrap().to_string();
+
+      match zyre.whisper(&uuid, Message::new()) {
+        Err(Error::WhisperToSelf) => {},
+        other => panic!("expected WhisperToSelf, got {:?}", other),
+      }
+    });
+  }
+
+  #[test]
+  fn io_error_not_started() {
+    let err: io::Error = Error::NotStarted.into();
+    assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+  }
+
+  #[test]
+  fn io_error_start_failed() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let errno = std::net::TcpListener::bind(listener.local_addr().unwrap()).unwrap_err().raw_os_error();
+
+    let err: io::Error = Error::StartFailed(errno).into();
+    assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+    let err: io::Error = Error::StartFailed(None).into();
+    assert_eq!(err.kind(), io::ErrorKind::Other);
+  }
+
+  #[test]
+  fn os_reason() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let errno = std::net::TcpListener::bind(listener.local_addr().unwrap()).unwrap_err().raw_os_error();
+
+    let reason = Error::StartFailed(errno).os_reason().unwrap();
+    assert!(!reason.is_empty());
+    assert!(reason.to_lowercase().contains("in use"), "unexpected reason {:?}", reason);
+
+    assert_eq!(Error::StartFailed(None).os_reason(), None);
+    assert_eq!(Error::NotStarted.os_reason(), None);
+  }
+
+  #[test]
+  fn message_new_destroy() {
+    let mut message = Message::new();
+    message.destroy();
+  }
+
+  #[test]
+  fn message_double_destroy() {
+    let mut message = Message::new();
+    message.destroy();
+    message.destroy();
+  }
+
+  #[test]
+  fn message_u32_be() {
+    let mut message = Message::new();
+    for &value in &[0, 1, 0x01020304, u32::MAX] {
+      message.push_u32_be(value);
+      assert_eq!(message.pop_u32_be().unwrap(), value);
+    }
+
+    message.push_u32_be(0x01020304);
+    assert_eq!(message.pop_bytes().unwrap(), vec![1, 2, 3, 4]);
+  }
+
+  #[test]
+  fn message_u64_be() {
+    let mut message = Message::new();
+    for &value in &[0, 1, 0x0102030405060708, u64::MAX] {
+      message.push_u64_be(value);
+      assert_eq!(message.pop_u64_be().unwrap(), value);
+    }
+  }
+
+  #[test]
+  fn message_pop_int_wrong_size() {
+    let mut message = Message::new();
+    message.push_u64_be(7);
+
+    match message.pop_u32_be() {
+      Err(Error::InvalidFrame) => {},
+      other => panic!("expected InvalidFrame, got {:?}", other),
+    }
+    match message.pop_u64_be() {
+      Err(Error::InvalidFrame) => {},
+      other => panic!("expected InvalidFrame, got {:?}", other),
+    }
+  }
+
+  /// Reads events until one matches, failing the test if none does within a
+  /// minute rather than hanging it.
+  pub fn read_until<F>(zyre:&mut Zyre, predicate:F) -> Event where F:Fn(&Event) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(60);
+    loop {
+      assert!(Instant::now() < deadline, "no matching event arrived");
+      if let Some(event) = zyre.read_event_timeout(Duration::from_millis(100)).unwrap() {
+        if predicate(&event) {
+          return event;
+        }
+      }
+    }
+  }
+
+  fn receive_shout(group:&str, frames:Vec<&str>) -> Event {
+    let port = unique_port();
+    let mut sender = ZyreBuilder::new().name("sender").port(port).build_started().unwrap();
+    let mut receiver = ZyreBuilder::new().name("receiver").port(port).build_started().unwrap();
+    sender.join(group).unwrap();
+    receiver.join(group).unwrap();
+
+    let sender_uuid = sender.uuid().unwrap().to_string();
+    let receiver_uuid = receiver.uuid().unwrap().to_string();
+    read_until(&mut sender, |event| {
+      event.event_type().unwrap() == "JOIN" && event.peer_uuid().unwrap() == receiver_uuid
+    });
+
+    sender.shout(group, Message::from_frames(frames).unwrap()).unwrap();
+
+    read_until(&mut receiver, |event| {
+      event.event_type().unwrap() == "SHOUT" && event.peer_uuid().unwrap() == sender_uuid
+    })
+  }
+
+  #[test]
+  fn ping_round_trip() {
+    let port = unique_port();
+    let (uuid_tx, uuid_rx) = std::sync::mpsc::channel();
+    let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+
+    let responder = std::thread::spawn(move || {
+      let mut zyre = ZyreBuilder::new().name("responder").port(port).build().unwrap();
+      zyre.start().unwrap();
+      uuid_tx.send(zyre.uuid().unwrap().to_string()).unwrap();
+
+      loop {
+        let event = zyre.read_event().unwrap();
+
+        if zyre.answer_ping(&event).unwrap() {
+          break;
+        }
+      }
+      done_rx.recv().ok();
+    });
+
+    let mut zyre = ZyreBuilder::new().name("pinger").port(port).build().unwrap();
+    zyre.start().unwrap();
+    let responder_uuid = uuid_rx.recv().unwrap();
+    read_until(&mut zyre, |event| {
+      event.event_type().unwrap() == "ENTER" && event.peer_uuid().unwrap() == responder_uuid
+    });
+
+    let rtt = zyre.ping(&responder_uuid, Duration::from_secs(5)).unwrap();
+    assert!(rtt < Duration::from_secs(1));
+
+    done_tx.send(()).unwrap();
+    responder.join().unwrap();
+  }
+
+  #[test]
+  fn is_leader() {
+    let port = unique_port();
+    let mut nodes: Vec<Zyre> = ["first", "second", "third"].iter()
+      .map(|name| ZyreBuilder::new().name(name).port(port).contest("ELECTED").join("ELECTED").build_started().unwrap())
+      .collect();
+
+    for node in nodes.iter_mut() {
+      read_until(node, |event| event.kind().unwrap() == EventType::Leader);
+    }
+
+    let leaders = nodes.iter().filter(|node| node.is_leader("ELECTED")).count();
+    assert_eq!(leaders, 1);
+    assert!(nodes.iter().all(|node| !node.is_leader("OTHER")));
+  }
+
+  #[test]
+  fn leadership_failover() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let port = unique_port();
+    let mut nodes = Vec::new();
+    let mut changes = Vec::new();
+    for name in &["first", "second", "third"] {
+      let mut node = ZyreBuilder::new().name(name).port(port).contest("ELECTED").join("ELECTED").build_started().unwrap();
+      let log = Rc::new(RefCell::new(Vec::new()));
+      let sink = log.clone();
+      node.on_leadership_change(move |group, is_leader| sink.borrow_mut().push((group.to_string(), is_leader)));
+      nodes.push(node);
+      changes.push(log);
+    }
+
+    for node in nodes.iter_mut() {
+      read_until(node, |event| event.kind().unwrap() == EventType::Leader);
+    }
+    let leader = nodes.iter().position(|node| node.is_leader("ELECTED")).unwrap();
+    assert_eq!(*changes[leader].borrow(), vec![("ELECTED".to_string(), true)]);
+
+    drop(nodes.remove(leader));
+    changes.remove(leader);
+
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let successor = loop {
+      assert!(Instant::now() < deadline, "leadership was never re-contested");
+      for node in nodes.iter_mut() {
+        node.read_event_timeout(Duration::from_millis(10)).unwrap();
+      }
+      if let Some(successor) = nodes.iter().position(|node| node.is_leader("ELECTED")) {
+        break successor;
+      }
+    };
+    assert_eq!(changes[successor].borrow().last(), Some(&("ELECTED".to_string(), true)));
+  }
+
+  #[test]
+  fn peer_last_seen() {
+    let port = unique_port();
+    let mut receiver = ZyreBuilder::new().name("receiver").port(port).join("LIVE").build_started().unwrap();
+    let mut sender = ZyreBuilder::new().name("sender").port(port).join("LIVE").build_started().unwrap();
+    let sender_uuid = sender.uuid().unwrap().to_string();
+    read_until(&mut sender, |event| event.kind().unwrap() == EventType::Join);
+    assert_eq!(receiver.peer_last_seen(&sender_uuid), None);
+
+    sender.shout("LIVE", Message::from_frames(vec!["here"]).unwrap()).unwrap();
+    read_until(&mut receiver, |event| event.kind().unwrap() == EventType::Shout);
+    let fresh = receiver.peer_last_seen(&sender_uuid).unwrap();
+    assert!(fresh < Duration::from_millis(500), "{:?}", fresh);
+
+    std::thread::sleep(Duration::from_millis(300));
+    let quiet = receiver.peer_last_seen(&sender_uuid).unwrap();
+    assert!(quiet >= fresh + Duration::from_millis(300), "{:?} then {:?}", fresh, quiet);
+    assert_eq!(receiver.peer_last_seen("0123456789ABCDEF0123456789ABCDEF"), None);
+  }
+
+  #[test]
+  fn replay_peers() {
+    let port = unique_port();
+    let mut late = ZyreBuilder::new().name("late").port(port).build_started().unwrap();
+    let first = ZyreBuilder::new().name("first").port(port).header("X-ROLE", "primary").build_started().unwrap();
+    let first_uuid = first.uuid().unwrap().to_string();
+
+    // Before any event is read, only what libzyre tracks is available.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while late.peers().unwrap().is_empty() {
+      assert!(Instant::now() < deadline, "peer never appeared");
+      std::thread::sleep(Duration::from_millis(10));
+    }
+    let replayed = late.replay_peers().unwrap();
+    assert_eq!(replayed.len(), 1);
+    assert_eq!(replayed[0].kind(), EventType::Enter);
+    assert_eq!(replayed[0].peer_uuid, first_uuid);
+    assert!(replayed[0].peer_addr.as_ref().unwrap().starts_with("tcp://"));
+
+    let enter = read_until(&mut late, |event| event.kind().unwrap() == EventType::Enter).snapshot().unwrap();
+    let replayed = late.replay_peers().unwrap();
+    assert_eq!(replayed, vec![OwnedEvent { frames: Vec::new(), ..enter }]);
+    assert_eq!(replayed[0].peer_name, "first");
+    assert_eq!(replayed[0].headers.get("X-ROLE").map(String::as_str), Some("primary"));
+  }
+
+  #[test]
+  fn is_sole_member() {
+    let port = unique_port();
+    let mut first = ZyreBuilder::new().name("first").port(port).join("SOLO").build_started().unwrap();
+    assert!(first.is_sole_member("SOLO").unwrap());
+    assert!(!first.is_sole_member("ELSEWHERE").unwrap());
+
+    let _second = ZyreBuilder::new().name("second").port(port).join("SOLO").build_started().unwrap();
+    read_until(&mut first, |event| event.kind().unwrap() == EventType::Join);
+    assert!(!first.is_sole_member("SOLO").unwrap());
+  }
+
+  #[test]
+  fn peers_sorted_by_name() {
+    let port = unique_port();
+    let mut observer = ZyreBuilder::new().name("observer").port(port).build_started().unwrap();
+    let _beta = ZyreBuilder::new().name("beta").port(port).build_started().unwrap();
+    let _alpha = ZyreBuilder::new().name("alpha").port(port).header("X-ROLE", "first").build_started().unwrap();
+
+    for _ in 0..2 {
+      read_until(&mut observer, |event| event.kind().unwrap() == EventType::Enter);
+    }
+
+    let peers = observer.peers_sorted_by_name().unwrap();
+    let names: Vec<&str> = peers.iter().map(|peer| peer.name.as_str()).collect();
+    assert_eq!(names, vec!["alpha", "beta"]);
+    assert_eq!(peers[0].headers.get("X-ROLE").map(String::as_str), Some("first"));
+  }
+
+  #[test]
+  fn subnet_of() {
+    assert_eq!(super::subnet_of("tcp://192.168.1.20:49152", 24), Some("192.168.1.0/24".to_string()));
+    assert_eq!(super::subnet_of("tcp://192.168.1.20:49152", 16), Some("192.168.0.0/16".to_string()));
+    assert_eq!(super::subnet_of("tcp://10.0.0.1:1", 0), Some("0.0.0.0/0".to_string()));
+    assert_eq!(super::subnet_of("tcp://10.0.0.1:1", 40), Some("10.0.0.1/32".to_string()));
+    assert_eq!(super::subnet_of("tcp://[fe80::1:2]:5670", 64), Some("fe80::/64".to_string()));
+    assert_eq!(super::subnet_of("tcp://not-an-ip:5670", 24), None);
+    assert_eq!(super::subnet_of("garbage", 24), None);
+  }
+
+  #[test]
+  fn peers_by_subnet() {
+    let port = unique_port();
+    let mut observer = ZyreBuilder::new().name("observer").port(port).build_started().unwrap();
+    let _first = ZyreBuilder::new().name("first").port(port).build_started().unwrap();
+    let _second = ZyreBuilder::new().name("second").port(port).build_started().unwrap();
+
+    for _ in 0..2 {
+      read_until(&mut observer, |event| event.kind().unwrap() == EventType::Enter);
+    }
+
+    // Every node here shares the host's address, so they land in one bucket.
+    let subnets = observer.peers_by_subnet(24).unwrap();
+    assert_eq!(subnets.len(), 1);
+    let (subnet, peers) = subnets.iter().next().unwrap();
+    assert!(subnet.ends_with("/24"), "unexpected subnet {}", subnet);
+    let names: Vec<&str> = peers.iter().map(|peer| peer.name.as_str()).collect();
+    assert_eq!(names, vec!["first", "second"]);
+  }
+
+  #[test]
+  fn discard_events() {
+    let port = unique_port();
+    let mut observer = ZyreBuilder::new().name("observer").port(port).build_started().unwrap();
+    let peer = ZyreBuilder::new().name("peer").port(port).join("ONE").join("TWO").build_started().unwrap();
+    read_until(&mut observer, |event| event.kind().unwrap() == EventType::Enter);
+    std::thread::sleep(Duration::from_millis(500));
+
+    assert!(observer.discard_events().unwrap() > 0);
+    assert!(observer.read_event_timeout(Duration::from_millis(10)).unwrap().is_none());
+    assert_eq!(observer.groups_of_peer(peer.uuid().unwrap()).unwrap(), vec!["ONE", "TWO"]);
+  }
+
+  #[test]
+  fn backlog_len() {
+    let port = unique_port();
+    let mut sender = ZyreBuilder::new().name("sender").port(port).join("FLOOD").build_started().unwrap();
+    let mut receiver = ZyreBuilder::new().name("receiver").port(port).join("FLOOD").build_started().unwrap();
+    read_until(&mut receiver, |event| event.kind().unwrap() == EventType::Join);
+    assert_eq!(receiver.backlog_len().unwrap(), 0);
+
+    for i in 0..20 {
+      sender.shout("FLOOD", Message::from_frames(vec![i.to_string()]).unwrap()).unwrap();
+    }
+    std::thread::sleep(Duration::from_millis(500));
+
+    assert!(receiver.backlog_len().unwrap() > 0);
+    receiver.discard_events().unwrap();
+    assert_eq!(receiver.backlog_len().unwrap(), 0);
+  }
+
+  #[test]
+  fn join_and_list() {
+    let port = unique_port();
+    let member = ZyreBuilder::new().name("member").port(port).join("SHARED").build_started().unwrap();
+    let mut newcomer = ZyreBuilder::new().name("newcomer").port(port).build_started().unwrap();
+
+    let peers = newcomer.join_and_list("SHARED", Duration::from_secs(2)).unwrap();
+    assert_eq!(peers.len(), 1);
+    assert_eq!(peers[0].uuid, member.uuid().unwrap());
+    assert_eq!(peers[0].name, "member");
+    assert!(newcomer.read_event_timeout(Duration::from_millis(10)).unwrap().is_some());
+  }
+
+  #[test]
+  fn peer_header_values() {
+    let port = unique_port();
+    let mut observer = ZyreBuilder::new().name("observer").port(port).build_started().unwrap();
+    let storage = ZyreBuilder::new().name("storage").port(port).header("X-ROLE", "storage").build_started().unwrap();
+    let compute = ZyreBuilder::new().name("compute").port(port).header("X-ROLE", "compute").build_started().unwrap();
+    let _plain = ZyreBuilder::new().name("plain").port(port).build_started().unwrap();
+
+    for _ in 0..3 {
+      read_until(&mut observer, |event| event.kind().unwrap() == EventType::Enter);
+    }
+
+    let mut expected = HashMap::new();
+    expected.insert(storage.uuid().unwrap().to_string(), "storage".to_string());
+    expected.insert(compute.uuid().unwrap().to_string(), "compute".to_string());
+    assert_eq!(observer.peer_header_values("X-ROLE").unwrap(), expected);
   }
-}
 
-impl Drop for Message {
-  fn drop(&mut self) {
-    self.destroy();
+  #[test]
+  fn restart_resets_state() {
+    let port = unique_port();
+    let mut zyre = ZyreBuilder::new().name("restarted").port(port).build_started().unwrap();
+    let peer = ZyreBuilder::new().name("peer").port(port).join("KEPT").build_started().unwrap();
+    let uuid = peer.uuid().unwrap().to_string();
+
+    read_until(&mut zyre, |event| event.kind().unwrap() == EventType::Join);
+    assert_eq!(zyre.groups_of_peer(&uuid).unwrap(), vec!["KEPT"]);
+
+    zyre.restart().unwrap();
+    assert!(zyre.groups_of_peer(&uuid).unwrap().is_empty());
+    assert!(zyre.peers_sorted_by_name().unwrap().is_empty());
+
+    read_until(&mut zyre, |event| event.kind().unwrap() == EventType::Join);
+    assert_eq!(zyre.groups_of_peer(&uuid).unwrap(), vec!["KEPT"]);
   }
-}
 
-#[cfg(test)]
-mod tests {
-  use super::*;
+  #[test]
+  fn compatible_peers() {
+    let port = unique_port();
+    let mut observer = ZyreBuilder::new().name("observer").port(port).build_started().unwrap();
+    let _old = ZyreBuilder::new().name("old").port(port).protocol_version(1).build_started().unwrap();
+    let new = ZyreBuilder::new().name("new").port(port).protocol_version(2).build_started().unwrap();
+    let _unversioned = ZyreBuilder::new().name("unversioned").port(port).build_started().unwrap();
+
+    for _ in 0..3 {
+      read_until(&mut observer, |event| event.kind().unwrap() == EventType::Enter);
+    }
+
+    assert_eq!(observer.compatible_peers(2).unwrap(), vec![new.uuid().unwrap().to_string()]);
+    assert_eq!(observer.compatible_peers(1).unwrap().len(), 2);
+  }
 
   #[test]
-  fn new_destroy() {
-    let mut zyre = Zyre::new(None).ok().unwrap();
-    zyre.destroy();
+  fn groups_of_peer() {
+    let port = unique_port();
+    let mut observer = ZyreBuilder::new().name("observer").port(port).build_started().unwrap();
+    let mut peer = ZyreBuilder::new().name("peer").port(port).join("RED").join("BLUE").build_started().unwrap();
+    let uuid = peer.uuid().unwrap().to_string();
+
+    for _ in 0..2 {
+      read_until(&mut observer, |event| event.kind().unwrap() == EventType::Join);
+    }
+    assert_eq!(observer.groups_of_peer(&uuid).unwrap(), vec!["BLUE", "RED"]);
+
+    peer.leave("RED").unwrap();
+    read_until(&mut observer, |event| event.kind().unwrap() == EventType::Leave);
+    assert_eq!(observer.groups_of_peer(&uuid).unwrap(), vec!["BLUE"]);
+    assert!(observer.groups_of_peer("unknown").unwrap().is_empty());
   }
 
   #[test]
-  fn double_destroy() {
-    let mut zyre = Zyre::new(None).ok().unwrap();
-    zyre.destroy();
-    zyre.destroy();
+  fn whisper_all_peers() {
+    let port = unique_port();
+    let mut sender = ZyreBuilder::new().name("sender").port(port).build_started().unwrap();
+    let mut receivers = [
+      ZyreBuilder::new().name("first").port(port).build_started().unwrap(),
+      ZyreBuilder::new().name("second").port(port).build_started().unwrap(),
+    ];
+
+    for _ in 0..receivers.len() {
+      read_until(&mut sender, |event| event.kind().unwrap() == EventType::Enter);
+    }
+
+    let sent = sender.whisper_all_peers(Message::from_frames(vec!["everyone"]).unwrap()).unwrap();
+    assert_eq!(sent, 2);
+
+    for receiver in receivers.iter_mut() {
+      let mut event = read_until(receiver, |event| event.kind().unwrap() == EventType::Whisper);
+      assert_eq!(event.take_message().unwrap().collect().unwrap(), vec!["everyone"]);
+    }
   }
 
-  fn acquire_context<F>(test_fn:F) where F:Fn(&mut Zyre) {
-    let mut zyre = Zyre::new(Some("test")).ok().unwrap();
+  #[test]
+  fn assert_frames_matches() {
+    let mut message = Message::new();
+    message.push("second").unwrap();
+    message.push("first").unwrap();
+    message.assert_frames(&[b"first", b"second"]);
+  }
 
-    test_fn(&mut zyre);
+  #[test]
+  #[should_panic(expected = "frame 1 differs: expected 5 bytes")]
+  fn assert_frames_names_index() {
+    let mut message = Message::new();
+    message.push("second").unwrap();
+    message.push("first").unwrap();
+    message.assert_frames(&[b"first", b"third"]);
+  }
 
-    zyre.destroy();
+  #[test]
+  #[should_panic(expected = "frame 2 missing")]
+  fn assert_frames_missing() {
+    let message = Message::from_frames(vec!["only", "two"]).unwrap();
+    message.assert_frames(&[b"two", b"only", b"three"]);
   }
 
   #[test]
-  fn uuid_length() {
-    acquire_context(|zyre:&mut Zyre| {
-      assert_eq!(zyre.uuid().unwrap().len(), 32);
+  fn collect_counted() {
+    let mut message = Message::from_frames(vec!["one", "two", "three"]).unwrap();
+    assert_eq!(message.len(), 3);
+    assert!(!message.is_empty());
+
+    let (frames, count) = message.collect_counted().unwrap();
+    assert_eq!(count, 3);
+    assert_eq!(frames.len(), count);
+    assert!(message.is_empty());
+  }
+
+  #[test]
+  fn into_strings() {
+    let mixed = || {
+      let mut message = Message::from_frames(vec!["first", "last"]).unwrap();
+      message.push_bytes(&[b'o', b'k', 0xff]);
+      message
+    };
+
+    assert_eq!(mixed().into_strings(true).unwrap(), vec!["first", "last", "ok\u{fffd}"]);
+    match mixed().into_strings(false) {
+      Err(Error::FromCStr(_)) => {},
+      other => panic!("expected FromCStr, got {:?}", other),
+    }
+    assert_eq!(Message::from_frames(vec!["a", "b"]).unwrap().into_strings(false).unwrap(), vec!["a", "b"]);
+  }
+
+  #[test]
+  fn matches_schema() {
+    let message = Message::from_frames(vec!["a", "b", "c"]).unwrap();
+    assert!(message.matches_schema(3..4));
+    assert!(message.matches_schema(1..10));
+    assert!(!message.matches_schema(0..3));
+    assert!(!message.matches_schema(4..6));
+  }
+
+  #[test]
+  fn validate_message() {
+    let port = unique_port();
+    let mut receiver = ZyreBuilder::new().name("receiver").port(port).build_started().unwrap();
+    let mut sender = ZyreBuilder::new().name("sender").port(port).build_started().unwrap();
+    let enter = read_until(&mut sender, |event| event.kind().unwrap() == EventType::Enter);
+    sender.whisper(enter.peer_uuid().unwrap(), Message::from_frames(vec!["a", "b", "c"]).unwrap()).unwrap();
+
+    let whisper = read_until(&mut receiver, |event| event.kind().unwrap() == EventType::Whisper);
+    whisper.validate_message(3).unwrap();
+    match whisper.validate_message(2) {
+      Err(err @ Error::UnexpectedFrames { expected: 2, actual: 3 }) => {
+        assert_eq!(err.to_string(), "Expected a message of 2 frames, got 3");
+      },
+      other => panic!("expected UnexpectedFrames, got {:?}", other),
+    }
+  }
+
+  #[test]
+  fn message_display() {
+    let message = Message::from_frames(vec!["abc", "de", ""]).unwrap();
+    assert_eq!(message.to_string(), "Message(3 frames, 5 bytes)");
+    assert_eq!(message.len(), 3);
+  }
+
+  #[test]
+  fn message_clone() {
+    let mut original = Message::from_frames(vec!["a", "b"]).unwrap();
+    let mut copy = original.clone();
+    assert_eq!(copy.collect().unwrap(), vec!["a", "b"]);
+    assert_eq!(original.collect().unwrap(), vec!["a", "b"]);
+  }
+
+  #[test]
+  fn scatter_gather() {
+    let port = unique_port();
+    let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+    let done_rx = std::sync::Arc::new(std::sync::Mutex::new(done_rx));
+
+    let responders: Vec<_> = ["left", "right"].iter().map(|name| {
+      let done_rx = done_rx.clone();
+      std::thread::spawn(move || {
+        let mut zyre = ZyreBuilder::new().name(name).port(port).join("WORK").build_started().unwrap();
+        let request = read_until(&mut zyre, |event| event.kind().unwrap() == EventType::Shout);
+        zyre.reply_to(&request, Message::from_frames(vec![*name]).unwrap()).unwrap();
+        done_rx.lock().unwrap().recv().ok();
+      })
+    }).collect();
+
+    let mut requester = ZyreBuilder::new().name("requester").port(port).join("WORK").build_started().unwrap();
+    for _ in 0..2 {
+      read_until(&mut requester, |event| event.kind().unwrap() == EventType::Join);
+    }
+
+    let replies = requester.scatter_gather("WORK", Message::from_frames(vec!["work"]).unwrap(), Duration::from_secs(5)).unwrap();
+    let mut names: Vec<String> = replies.into_iter().map(|(_, mut reply)| reply.pop().unwrap()).collect();
+    names.sort();
+    assert_eq!(names, vec!["left", "right"]);
+
+    drop(done_tx);
+    for responder in responders {
+      responder.join().unwrap();
+    }
+  }
+
+  #[test]
+  fn on_request() {
+    let port = unique_port();
+    let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+
+    let responder = std::thread::spawn(move || {
+      let mut zyre = ZyreBuilder::new().name("responder").port(port).join("WORK").build_started().unwrap();
+      zyre.on_request("WORK", |request| {
+        let mut request = request.clone();
+        let word = request.pop().ok()?;
+        Message::from_frames(vec![word.to_uppercase()]).ok()
+      });
+
+      while done_rx.try_recv().is_err() {
+        zyre.read_event_timeout(Duration::from_millis(10)).unwrap();
+      }
     });
+
+    let mut requester = ZyreBuilder::new().name("requester").port(port).join("WORK").build_started().unwrap();
+    read_until(&mut requester, |event| event.kind().unwrap() == EventType::Join);
+
+    let mut replies = requester.scatter_gather("WORK", Message::from_frames(vec!["quiet"]).unwrap(), Duration::from_secs(5)).unwrap();
+    assert_eq!(replies.len(), 1);
+    assert_eq!(replies[0].1.collect().unwrap(), vec!["QUIET"]);
+
+    done_tx.send(()).unwrap();
+    responder.join().unwrap();
   }
 
   #[test]
-  fn name_value() {
-    acquire_context(|zyre:&mut Zyre| {
-      assert_eq!(zyre.name().unwrap(), "test");
+  fn on_request_panics() {
+    let port = unique_port();
+    let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+
+    let responder = std::thread::spawn(move || {
+      let mut zyre = ZyreBuilder::new().name("responder").port(port).join("WORK").build_started().unwrap();
+      zyre.on_request("WORK", |request| {
+        let word = request.clone().pop().ok()?;
+        if word == "crash" {
+          panic!("cannot handle {}", word);
+        }
+        Message::from_frames(vec![word.to_uppercase()]).ok()
+      });
+
+      while done_rx.try_recv().is_err() {
+        zyre.read_event_timeout(Duration::from_millis(10)).unwrap();
+      }
+      zyre.last_panic().map(String::from)
     });
+
+    let mut requester = ZyreBuilder::new().name("requester").port(port).join("WORK").build_started().unwrap();
+    read_until(&mut requester, |event| event.kind().unwrap() == EventType::Join);
+
+    let crashed = requester.scatter_gather("WORK", Message::from_frames(vec!["crash"]).unwrap(), Duration::from_millis(500)).unwrap();
+    assert!(crashed.is_empty());
+    let mut replies = requester.scatter_gather("WORK", Message::from_frames(vec!["still"]).unwrap(), Duration::from_secs(5)).unwrap();
+    assert_eq!(replies[0].1.collect().unwrap(), vec!["STILL"]);
+
+    done_tx.send(()).unwrap();
+    assert_eq!(responder.join().unwrap(), Some("cannot handle crash".to_string()));
   }
 
   #[test]
-  fn start_stop() {
-    acquire_context(|zyre:&mut Zyre| {
-      zyre.start().ok();
-      zyre.stop();
+  fn ping_responder() {
+    let port = unique_port();
+    let _responder = ZyreActor::spawn(move || {
+      let mut zyre = ZyreBuilder::new().name("auto-responder").port(port).build()?;
+      zyre.enable_ping_responder();
+      Ok(zyre)
+    }).unwrap();
+
+    let mut zyre = ZyreBuilder::new().name("pinger").port(port).build().unwrap();
+    zyre.start().unwrap();
+    let enter = read_until(&mut zyre, |event| {
+      event.event_type().unwrap() == "ENTER" && event.peer_name().unwrap() == "auto-responder"
     });
+
+    let rtt = zyre.ping(enter.peer_uuid().unwrap(), Duration::from_secs(5)).unwrap();
+    assert!(rtt < Duration::from_secs(1));
   }
 
-  fn acquire_started_context<F>(test_fn:F) where F:Fn(&mut Zyre) {
-    acquire_context(|zyre:&mut Zyre| {
-      zyre.start().ok();
+  #[test]
+  fn can_reach() {
+    let port = unique_port();
+    let _responder = ZyreActor::spawn(move || {
+      let mut zyre = ZyreBuilder::new().name("reachable").port(port).build()?;
+      zyre.enable_ping_responder();
+      Ok(zyre)
+    }).unwrap();
+    let _silent = ZyreBuilder::new().name("silent").port(port).build_started().unwrap();
 
-      test_fn(zyre);
+    let mut zyre = ZyreBuilder::new().name("prober").port(port).build_started().unwrap();
+    let mut uuids = HashMap::new();
+    while uuids.len() < 2 {
+      let enter = read_until(&mut zyre, |event| event.kind().unwrap() == EventType::Enter);
+      uuids.insert(enter.peer_name().unwrap().to_string(), enter.peer_uuid().unwrap().to_string());
+    }
 
-      zyre.stop();
+    assert!(zyre.can_reach(&uuids["reachable"], Duration::from_secs(5)).unwrap());
+    assert!(!zyre.can_reach(&uuids["silent"], Duration::from_millis(200)).unwrap());
+  }
+
+  #[test]
+  fn join_retry() {
+    let id = unique_port();
+    let hub_endpoint = format!("inproc://retry-hub-{}", id);
+    let _hub = ZyreBuilder::new().name("hub").endpoint(format!("inproc://hub-{}", id)).gossip_bind(&hub_endpoint).join("PUBSUB").build_started().unwrap();
+    let mut client = ZyreBuilder::new().name("client").endpoint(format!("inproc://client-{}", id)).gossip_connect(&hub_endpoint).build_started().unwrap();
+
+    client.join_retry("PUBSUB", 5, Duration::from_secs(1)).unwrap();
+    assert_eq!(client.peers_by_group("PUBSUB").unwrap().len(), 1);
+
+    let mut alone = ZyreBuilder::new().name("alone").port(unique_port()).build_started().unwrap();
+    match alone.join_retry("EMPTY", 2, Duration::from_millis(50)) {
+      Err(Error::JoinFailed) => {},
+      other => panic!("expected JoinFailed, got {:?}", other),
+    }
+    assert_eq!(alone.own_groups().unwrap(), vec!["EMPTY"]);
+  }
+
+  #[test]
+  fn leave_all_then_stop() {
+    let port = unique_port();
+    let mut observer = ZyreBuilder::new().name("observer").port(port).build().unwrap();
+    let mut leaver = ZyreBuilder::new().name("leaver").port(port).build().unwrap();
+    observer.start().unwrap();
+    leaver.start().unwrap();
+    leaver.join("FIRST").unwrap();
+    leaver.join("SECOND").unwrap();
+
+    let leaver_uuid = leaver.uuid().unwrap().to_string();
+    let mut joined = BTreeSet::new();
+    while joined.len() < 2 {
+      let event = observer.read_event().unwrap();
+      if event.event_type().unwrap() == "JOIN" && event.peer_uuid().unwrap() == leaver_uuid {
+        joined.insert(event.group().unwrap().to_string());
+      }
+    }
+
+    leaver.leave_all_then_stop().unwrap();
+
+    let mut left = BTreeSet::new();
+    loop {
+      let event = observer.read_event().unwrap();
+      if event.peer_uuid().unwrap() != leaver_uuid {
+        continue;
+      }
+
+      match event.event_type().unwrap() {
+        "LEAVE" => { left.insert(event.group().unwrap().to_string()); },
+        "EXIT" => break,
+        _ => {},
+      }
+    }
+    assert_eq!(left, joined);
+  }
+
+  #[test]
+  fn over_long_group() {
+    let mut zyre = ZyreBuilder::new().name("long").port(unique_port()).build_started().unwrap();
+    let longest = "g".repeat(MAX_GROUP_LEN);
+    let too_long = "g".repeat(MAX_GROUP_LEN + 1);
+
+    zyre.join(&longest).unwrap();
+    zyre.leave(&longest).unwrap();
+
+    for result in [
+      zyre.join(&too_long),
+      zyre.leave(&too_long),
+      zyre.shout(&too_long, Message::from_frames(vec!["hi"]).unwrap()),
+    ] {
+      match result {
+        Err(Error::InvalidGroup) => {},
+        other => panic!("expected InvalidGroup, got {:?}", other),
+      }
+    }
+    assert!(zyre.own_groups().unwrap().is_empty());
+  }
+
+  #[test]
+  fn set_groups() {
+    let port = unique_port();
+    let mut observer = ZyreBuilder::new().name("observer").port(port).build_started().unwrap();
+    let mut node = ZyreBuilder::new().name("node").port(port).join("A").join("B").build_started().unwrap();
+    let node_uuid = node.uuid().unwrap().to_string();
+    for _ in 0..2 {
+      read_until(&mut observer, |event| event.kind().unwrap() == EventType::Join);
+    }
+
+    node.set_groups(vec!["B", "C"]).unwrap();
+    let mut groups = node.own_groups().unwrap();
+    groups.sort();
+    assert_eq!(groups, vec!["B", "C"]);
+
+    // Joining a marker group afterwards bounds the changes to look at.
+    node.join("DONE").unwrap();
+    let mut changes = HashSet::new();
+    loop {
+      let event = observer.read_event().unwrap();
+      if event.peer_uuid().unwrap() != node_uuid {
+        continue;
+      }
+
+      match (event.kind().unwrap(), event.group().unwrap()) {
+        (EventType::Join, "DONE") => break,
+        (kind @ EventType::Join, group) | (kind @ EventType::Leave, group) => {
+          changes.insert((kind, group.to_string()));
+        },
+        _ => {},
+      }
+    }
+
+    let expected:HashSet<(EventType, String)> = vec![
+      (EventType::Leave, "A".to_string()),
+      (EventType::Join, "C".to_string()),
+    ].into_iter().collect();
+    assert_eq!(changes, expected);
+  }
+
+  #[test]
+  fn max_inbound_frame() {
+    let port = unique_port();
+    let mut sender = ZyreBuilder::new().name("sender").port(port).build().unwrap();
+    let mut receiver = ZyreBuilder::new().name("receiver").port(port).max_inbound_frame(1024).build().unwrap();
+    sender.start().unwrap();
+    receiver.start().unwrap();
+    sender.join("LIMITED").unwrap();
+    receiver.join("LIMITED").unwrap();
+
+    let receiver_uuid = receiver.uuid().unwrap().to_string();
+    read_until(&mut sender, |event| {
+      event.event_type().unwrap() == "JOIN" && event.peer_uuid().unwrap() == receiver_uuid
     });
+
+    let oversized = "x".repeat(4096);
+    sender.shout("LIMITED", Message::from_frames(vec![oversized]).unwrap()).unwrap();
+    sender.shout("LIMITED", Message::from_frames(vec!["small"]).unwrap()).unwrap();
+
+    let event = read_until(&mut receiver, |event| event.event_type().unwrap() == "SHOUT");
+    assert_eq!(event.message_ref().unwrap().collect().unwrap(), vec!["small"]);
+    assert_eq!(receiver.stats().rejected, 1);
   }
 
   #[test]
-  fn join_leave() {
-    acquire_started_context(|zyre:&mut Zyre| {
-      zyre.join("GLOBAL").ok();
-      zyre.leave("GLOBAL").ok();
+  fn max_frames() {
+    let port = unique_port();
+    let mut sender = ZyreBuilder::new().name("sender").port(port).join("LIMITED").build_started().unwrap();
+    let mut receiver = ZyreBuilder::new().name("receiver").port(port).max_frames(3).join("LIMITED").build_started().unwrap();
+
+    let receiver_uuid = receiver.uuid().unwrap().to_string();
+    read_until(&mut sender, |event| {
+      event.event_type().unwrap() == "JOIN" && event.peer_uuid().unwrap() == receiver_uuid
     });
+
+    sender.shout("LIMITED", Message::from_frames(vec!["a"; 1000]).unwrap()).unwrap();
+    sender.shout("LIMITED", Message::from_frames(vec!["a", "b", "c"]).unwrap()).unwrap();
+
+    let event = read_until(&mut receiver, |event| event.event_type().unwrap() == "SHOUT");
+    assert_eq!(event.message_ref().unwrap().size(), 3);
+    assert_eq!(receiver.stats().rejected, 1);
   }
 
   #[test]
-  fn read_event() {
-    acquire_started_context(|zyre:&mut Zyre| {
-      zyre.read_event().ok();
+  fn mute_group() {
+    let port = unique_port();
+    let mut sender = ZyreBuilder::new().name("sender").port(port).join("NOISY").build_started().unwrap();
+    let mut receiver = ZyreBuilder::new().name("receiver").port(port).join("NOISY").build_started().unwrap();
+    read_until(&mut sender, |event| event.kind().unwrap() == EventType::Join);
+
+    sender.mute_group("NOISY");
+    sender.shout("NOISY", Message::from_frames(vec!["hushed"]).unwrap()).unwrap();
+    assert_eq!(sender.stats().muted, 1);
+
+    sender.unmute_group("NOISY");
+    sender.shout("NOISY", Message::from_frames(vec!["heard"]).unwrap()).unwrap();
+
+    let mut event = read_until(&mut receiver, |event| event.kind().unwrap() == EventType::Shout);
+    assert_eq!(event.take_message().unwrap().collect().unwrap(), vec!["heard"]);
+  }
+
+  #[test]
+  fn shout_empty() {
+    let port = unique_port();
+    let mut sender = ZyreBuilder::new().name("sender").port(port).join("KEEPALIVE").build_started().unwrap();
+    let mut receiver = ZyreBuilder::new().name("receiver").port(port).join("KEEPALIVE").build_started().unwrap();
+    read_until(&mut sender, |event| event.kind().unwrap() == EventType::Join);
+
+    sender.shout_empty("KEEPALIVE").unwrap();
+    sender.shout("KEEPALIVE", Message::new()).unwrap();
+
+    for _ in 0..2 {
+      let event = read_until(&mut receiver, |event| event.kind().unwrap() == EventType::Shout);
+      assert_eq!(event.message_ref().unwrap().content_size(), 0);
+      assert_eq!(event.raw_message_frames(), vec![Vec::<u8>::new()]);
+    }
+  }
+
+  #[test]
+  fn shout_stats() {
+    let port = unique_port();
+    let mut sender = ZyreBuilder::new().name("sender").port(port).build().unwrap();
+    let mut monitor = ZyreBuilder::new().name("monitor").port(port).build().unwrap();
+    sender.start().unwrap();
+    monitor.start().unwrap();
+    sender.join("STATS").unwrap();
+    monitor.join("STATS").unwrap();
+
+    let monitor_uuid = monitor.uuid().unwrap().to_string();
+    read_until(&mut sender, |event| {
+      event.event_type().unwrap() == "JOIN" && event.peer_uuid().unwrap() == monitor_uuid
     });
+    let expected = sender.stats();
+    sender.shout_stats("STATS").unwrap();
+
+    let event = read_until(&mut monitor, |event| event.event_type().unwrap() == "SHOUT");
+    let report = event.command::<StatsReport>().unwrap();
+    assert_eq!(report.peers, 1);
+    assert_eq!(report.stats, expected);
   }
 
   #[test]
-  fn event_read_destroy() {
-    acquire_started_context(|zyre:&mut Zyre| {
-      let mut event = zyre.read_event().unwrap();
-      event.destroy();
+  fn take_stats() {
+    let port = unique_port();
+    let mut first = ZyreBuilder::new().name("first").port(port).build().unwrap();
+    let mut second = ZyreBuilder::new().name("second").port(port).build().unwrap();
+    first.start().unwrap();
+    second.start().unwrap();
+    read_until(&mut first, |event| event.event_type().unwrap() == "ENTER");
+
+    let taken = first.take_stats();
+    assert!(taken.events > 0);
+    assert_eq!(first.stats(), Stats::default());
+  }
+
+  #[test]
+  fn stats_report_frames() {
+    let report = StatsReport {
+      peers: 2,
+      stats: Stats { events: 10, bytes: 512, rejected: 1, coalesced: 3, foreign: 4, muted: 5 },
+    };
+    let frames = report.to_frames();
+    let frames:Vec<&str> = frames.iter().map(String::as_str).collect();
+
+    assert_eq!(StatsReport::from_frames(&frames), Some(report));
+    assert_eq!(StatsReport::from_frames(&["OTHER"]), None);
+  }
+
+  #[test]
+  fn coalesce_shouts() {
+    let port = unique_port();
+    let mut sender = ZyreBuilder::new().name("sender").port(port).build().unwrap();
+    let mut receiver = ZyreBuilder::new().name("receiver").port(port).build().unwrap();
+    receiver.coalesce("STATE", Duration::from_millis(200));
+    sender.start().unwrap();
+    receiver.start().unwrap();
+    sender.join("STATE").unwrap();
+    receiver.join("STATE").unwrap();
+
+    let receiver_uuid = receiver.uuid().unwrap().to_string();
+    read_until(&mut sender, |event| {
+      event.event_type().unwrap() == "JOIN" && event.peer_uuid().unwrap() == receiver_uuid
     });
+
+    for i in 0..50 {
+      sender.shout("STATE", Message::from_frames(vec![i.to_string()]).unwrap()).unwrap();
+    }
+
+    let mut seen = 0;
+    loop {
+      let event = read_until(&mut receiver, |event| event.event_type().unwrap() == "SHOUT");
+      seen += 1;
+
+      if event.message_ref().unwrap().collect().unwrap() == vec!["49"] {
+        break;
+      }
+    }
+    assert!(seen < 10, "saw {} shouts", seen);
+    assert_eq!(receiver.stats().coalesced, 50 - seen);
   }
 
   #[test]
-  fn event_double_destroy() {
-    acquire_started_context(|zyre:&mut Zyre| {
-      let mut event = zyre.read_event().unwrap();
-      event.destroy();
-      event.destroy();
+  fn group_summary() {
+    let port = unique_port();
+    let mut first = ZyreBuilder::new().name("first").port(port).build().unwrap();
+    let mut second = ZyreBuilder::new().name("second").port(port).build().unwrap();
+    first.start().unwrap();
+    second.start().unwrap();
+    first.join_all(vec!["X", "Y"]);
+    second.join_all(vec!["Y", "Z"]);
+
+    let second_uuid = second.uuid().unwrap().to_string();
+    let mut joined = BTreeSet::new();
+    while joined.len() < 2 {
+      let event = first.read_event().unwrap();
+      if event.event_type().unwrap() == "JOIN" && event.peer_uuid().unwrap() == second_uuid {
+        joined.insert(event.group().unwrap().to_string());
+      }
+    }
+
+    let summary = first.group_summary().unwrap();
+    assert_eq!(summary.len(), 3);
+    assert_eq!(summary["X"], 1);
+    assert_eq!(summary["Y"], 2);
+    assert_eq!(summary["Z"], 1);
+  }
+
+  #[test]
+  fn event_delivery() {
+    let port = unique_port();
+    let mut sender = ZyreBuilder::new().name("sender").port(port).build().unwrap();
+    let mut receiver = ZyreBuilder::new().name("receiver").port(port).build().unwrap();
+    sender.start().unwrap();
+    receiver.start().unwrap();
+    sender.join("DELIVERY").unwrap();
+    receiver.join("DELIVERY").unwrap();
+
+    let receiver_uuid = receiver.uuid().unwrap().to_string();
+    let join = read_until(&mut sender, |event| {
+      event.event_type().unwrap() == "JOIN" && event.peer_uuid().unwrap() == receiver_uuid
     });
+    assert_eq!(join.kind().unwrap(), EventType::Join);
+    assert_eq!(join.delivery(), None);
+
+    sender.shout("DELIVERY", Message::from_frames(vec!["all"]).unwrap()).unwrap();
+    sender.whisper(&receiver_uuid, Message::from_frames(vec!["one"]).unwrap()).unwrap();
+
+    let sender_uuid = sender.uuid().unwrap().to_string();
+    let shout = read_until(&mut receiver, |event| event.kind().unwrap() == EventType::Shout);
+    assert_eq!(shout.delivery(), Some(Delivery::Shout { from: sender_uuid.clone(), group: "DELIVERY".to_string() }));
+
+    let whisper = read_until(&mut receiver, |event| event.kind().unwrap() == EventType::Whisper);
+    assert_eq!(whisper.delivery(), Some(Delivery::Whisper { from: sender_uuid }));
   }
 
   #[test]
-  fn message_new_destroy() {
-    let mut message = Message::new();
-    message.destroy();
+  fn event_message_ref() {
+    let event = receive_shout("MESSAGE-REF", vec!["hello"]);
+    let message = event.message_ref().unwrap();
+    assert_eq!(message.size(), 1);
+    assert_eq!(message.collect().unwrap(), vec!["hello"]);
   }
 
   #[test]
-  fn message_double_destroy() {
+  fn raw_message_frames() {
+    let port = unique_port();
+    let mut sender = ZyreBuilder::new().name("sender").port(port).join("RAW").build_started().unwrap();
+    let mut receiver = ZyreBuilder::new().name("receiver").port(port).join("RAW").build_started().unwrap();
+    read_until(&mut sender, |event| event.kind().unwrap() == EventType::Join);
+
+    let mut msg = Message::new();
+    msg.push_bytes(&[0, 159, 255]);
+    msg.push("text").unwrap();
+    sender.shout("RAW", msg).unwrap();
+
+    let event = read_until(&mut receiver, |event| event.kind().unwrap() == EventType::Shout);
+    assert_eq!(event.raw_message_frames(), vec![b"text".to_vec(), vec![0, 159, 255]]);
+    assert!(event.message_ref().is_some());
+  }
+
+  #[test]
+  fn event_drops_before_taken_message() {
+    let mut event = receive_shout("DROP-EVENT-FIRST", vec!["hello"]);
+    let mut message = event.take_message().unwrap();
+    drop(event);
+    assert_eq!(message.collect().unwrap(), vec!["hello"]);
+  }
+
+  #[test]
+  fn taken_message_drops_before_event() {
+    let mut event = receive_shout("DROP-MESSAGE-FIRST", vec!["hello"]);
+    let message = event.take_message().unwrap();
+    drop(message);
+    assert!(event.message_ref().is_none());
+    assert!(event.take_message().is_none());
+    drop(event);
+  }
+
+  #[test]
+  fn base64() {
+    let cases: &[(&[u8], &str)] = &[
+      (b"", ""),
+      (b"f", "Zg=="),
+      (b"fo", "Zm8="),
+      (b"foo", "Zm9v"),
+      (b"foob", "Zm9vYg=="),
+      (&[0, 255, 128, 7], "AP+ABw=="),
+    ];
+    for &(bytes, encoded) in cases {
+      assert_eq!(base64_encode(bytes), encoded);
+      assert_eq!(base64_decode(encoded).unwrap(), bytes);
+    }
+
+    for bad in &["Zg=", "Z===", "Zg==Zm8=", "Zm9*"] {
+      match base64_decode(bad) {
+        Err(Error::InvalidEncoding) => {},
+        other => panic!("expected InvalidEncoding for {:?}, got {:?}", bad, other),
+      }
+    }
+  }
+
+  #[test]
+  fn header_bytes() {
+    let port = unique_port();
+    let mut observer = ZyreBuilder::new().name("observer").port(port).build_started().unwrap();
+    let mut peer = ZyreBuilder::new().name("peer").port(port).header("X-TEXT", "not base64!").build().unwrap();
+    let key = [0u8, 1, 2, 253, 254, 255];
+    peer.set_header_bytes("X-KEY", &key).unwrap();
+    peer.start().unwrap();
+
+    let enter = read_until(&mut observer, |event| event.kind().unwrap() == EventType::Enter);
+    assert_eq!(enter.header_bytes("X-KEY").unwrap(), Some(key.to_vec()));
+    assert_eq!(enter.header_bytes("X-MISSING").unwrap(), None);
+    match enter.header_bytes("X-TEXT") {
+      Err(Error::InvalidEncoding) => {},
+      other => panic!("expected InvalidEncoding, got {:?}", other),
+    }
+  }
+
+  #[test]
+  fn headers_as_env() {
+    let port = unique_port();
+    let mut observer = ZyreBuilder::new().name("observer").port(port).build_started().unwrap();
+    let _peer = ZyreBuilder::new().name("peer").port(port)
+      .display_name("Front Desk")
+      .header("x-note", "line one\nline two")
+      .header("9lives", "yes")
+      .build_started().unwrap();
+
+    let enter = read_until(&mut observer, |event| event.kind().unwrap() == EventType::Enter);
+    assert_eq!(enter.headers_as_env().unwrap(), vec![
+      "X_DISPLAY_NAME=Front Desk",
+      "X_NOTE=line one\\nline two",
+      "_9LIVES=yes",
+    ]);
+  }
+
+  #[test]
+  fn owned_event_round_trip() {
+    let mut headers = HashMap::new();
+    headers.insert("X-APP".to_string(), "demo".to_string());
+    headers.insert("X-DISPLAY-NAME".to_string(), "Front Desk".to_string());
+
+    let enter = OwnedEvent {
+      event_type: "ENTER".to_string(),
+      peer_uuid: "ABCD".to_string(),
+      peer_name: "peer".to_string(),
+      peer_addr: Some("tcp://127.0.0.1:49152".to_string()),
+      headers,
+      group: None,
+      frames: vec![],
+    };
+    assert_eq!(OwnedEvent::decode(&enter.encode()).unwrap(), enter);
+
+    let shout = OwnedEvent {
+      event_type: "SHOUT".to_string(),
+      peer_uuid: "ABCD".to_string(),
+      peer_name: "peer".to_string(),
+      peer_addr: None,
+      headers: HashMap::new(),
+      group: Some("GLOBAL".to_string()),
+      frames: vec![b"hello".to_vec(), vec![0, 255, 1]],
+    };
+    let bytes = shout.encode();
+    assert_eq!(OwnedEvent::decode(&bytes).unwrap(), shout);
+
+    match OwnedEvent::decode(&bytes[..bytes.len() - 1]) {
+      Err(Error::InvalidEncoding) => {},
+      other => panic!("expected InvalidEncoding, got {:?}", other),
+    }
+  }
+
+  #[test]
+  fn utf8_policy() {
     let mut message = Message::new();
-    message.destroy();
-    message.destroy();
+    message.push_bytes(b"caf\xe9");
+    match message.pop() {
+      Err(Error::FromCStr(_)) => {},
+      other => panic!("expected FromCStr, got {:?}", other),
+    }
+
+    let mut message = Message::new();
+    message.set_utf8_policy(Utf8Policy::Lossy);
+    message.push_bytes(b"caf\xe9");
+    message.push("ok").unwrap();
+    assert_eq!(message.collect().unwrap(), vec!["caf\u{fffd}", "ok"]);
+
+    let zyre = ZyreBuilder::new().name("lossy").utf8_policy(Utf8Policy::Lossy).build().unwrap();
+    assert_eq!(zyre.utf8_policy(), Utf8Policy::Lossy);
+    assert_eq!(zyre.name().unwrap(), "lossy");
   }
 }
@@ -0,0 +1,114 @@
+use std::io::{ self, BufRead, Write };
+use std::time::Duration;
+use super::{ Event, Message, Result, Zyre };
+
+const HELP: &str = "commands: join GROUP, leave GROUP, shout GROUP MSG, whisper UUID MSG, peers, quit";
+
+impl Zyre {
+  /// Drives the node from commands typed on stdin, printing events to
+  /// stdout as they arrive. See `repl_with` for the commands.
+  pub fn repl(&mut self) -> Result<()> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    self.repl_with(stdin.lock(), stdout.lock())
+  }
+
+  /// Reads one command per line from `input` until `quit` or end of input:
+  /// `join GROUP`, `leave GROUP`, `shout GROUP MSG`, `whisper UUID MSG` and
+  /// `peers`. Events that arrived in the meantime are written to `output`
+  /// after each command. A failed or unknown command is reported and the
+  /// loop carries on; only I/O and read errors end it.
+  pub fn repl_with<R, W>(&mut self, input:R, mut output:W) -> Result<()>
+  where R:BufRead, W:Write {
+    for line in input.lines() {
+      let line = line?;
+      let mut words = line.trim().splitn(3, ' ');
+
+      let outcome = match (words.next(), words.next(), words.next()) {
+        (Some("quit"), None, None) => break,
+        (Some(""), None, None) => Ok(()),
+        (Some("join"), Some(group), None) => self.join(group),
+        (Some("leave"), Some(group), None) => self.leave(group),
+        (Some("shout"), Some(group), Some(text)) => {
+          Message::from_frames(vec![text]).and_then(|msg| self.shout(group, msg))
+        },
+        (Some("whisper"), Some(peer), Some(text)) => {
+          Message::from_frames(vec![text]).and_then(|msg| self.whisper(peer, msg))
+        },
+        (Some("peers"), None, None) => match self.peers_sorted_by_name() {
+          Ok(peers) => {
+            for peer in peers {
+              writeln!(output, "{} {} {}", peer.uuid, peer.name, peer.address)?;
+            }
+            Ok(())
+          },
+          Err(err) => Err(err),
+        },
+        _ => {
+          writeln!(output, "unknown command: {}", line.trim())?;
+          writeln!(output, "{}", HELP)?;
+          Ok(())
+        },
+      };
+
+      if let Err(err) = outcome {
+        writeln!(output, "error: {}", err)?;
+      }
+
+      while let Some(event) = self.read_event_timeout(Duration::from_millis(0))? {
+        print_event(&mut output, &event)?;
+      }
+      output.flush()?;
+    }
+
+    Ok(())
+  }
+}
+
+/// One line per event: its type, the peer's name, the group when there is
+/// one, then each message frame.
+fn print_event<W>(output:&mut W, event:&Event) -> io::Result<()> where W:Write {
+  write!(output, "{} {}", event.event_type().unwrap_or("?"), event.peer_name().unwrap_or_default())?;
+
+  if let Ok(group) = event.group() {
+    write!(output, " [{}]", group)?;
+  }
+
+  if let Some(msg) = event.message_ref() {
+    for frame in msg.frames() {
+      write!(output, " {}", String::from_utf8_lossy(frame))?;
+    }
+  }
+
+  writeln!(output)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Cursor;
+  use super::super::{ EventType, ZyreBuilder };
+  use super::super::tests::{ read_until, unique_port };
+
+  #[test]
+  fn scripted_commands() {
+    let port = unique_port();
+    let mut listener = ZyreBuilder::new().name("listener").port(port).join("ROOM").build_started().unwrap();
+    let mut zyre = ZyreBuilder::new().name("repl").port(port).build_started().unwrap();
+    let uuid = listener.uuid().unwrap().to_string();
+    read_until(&mut zyre, |event| event.kind().unwrap() == EventType::Join);
+
+    let script = format!("join ROOM\npeers\nwhisper {} psst\nshout ROOM hello all\nnope\nquit\nshout ROOM unreached\n", uuid);
+    let mut output = Vec::new();
+    zyre.repl_with(Cursor::new(script), &mut output).unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains(&format!("{} listener ", uuid)), "{}", output);
+    assert!(output.contains("unknown command: nope"), "{}", output);
+
+    let whisper = read_until(&mut listener, |event| event.kind().unwrap() == EventType::Whisper);
+    assert_eq!(whisper.message_ref().unwrap().frames(), vec![&b"psst"[..]]);
+    let shout = read_until(&mut listener, |event| event.kind().unwrap() == EventType::Shout);
+    assert_eq!(shout.message_ref().unwrap().frames(), vec![&b"hello all"[..]]);
+    assert!(zyre.own_groups().unwrap().contains(&"ROOM".to_string()));
+  }
+}
@@ -0,0 +1,171 @@
+use std::thread;
+use std::time::{ Duration, Instant };
+use log::warn;
+use super::{ catch_handler, Error, Event, Result, Zyre };
+
+/// How `Zyre::run_with` drives its handler.
+#[derive(Clone, Debug, Default)]
+pub struct RunOptions {
+  /// Log a warning whenever the handler takes longer than this on one event.
+  pub slow_threshold: Option<Duration>,
+  /// Give other work on this thread a turn after every this many events.
+  pub yield_every: Option<usize>,
+  /// How to give way when `yield_every` comes due; `thread::yield_now` if
+  /// unset.
+  pub on_yield: Option<fn()>,
+  /// End the loop with `Error::HandlerPanicked` when the handler panics,
+  /// instead of logging it and carrying on with the next event.
+  pub stop_on_panic: bool,
+}
+
+impl Zyre {
+  /// Reads events and passes each to `handler` until it returns `false` or
+  /// a read fails. The handler gets the node too, so it can reply. A panic
+  /// in the handler is caught and logged, and recorded in `last_panic`.
+  pub fn run<F>(&mut self, handler:F) -> Result<()> where F:FnMut(&mut Zyre, Event) -> bool {
+    self.run_with(&RunOptions::default(), handler)
+  }
+
+  /// `run`, logging a warning (through the `log` crate) for every event the
+  /// handler spends longer than `threshold` on. Slow handlers make events
+  /// back up in libzyre, so this points at the cause of growing latency.
+  pub fn run_timed<F>(&mut self, threshold:Duration, handler:F) -> Result<()> where F:FnMut(&mut Zyre, Event) -> bool {
+    self.run_with(&RunOptions { slow_threshold: Some(threshold), ..RunOptions::default() }, handler)
+  }
+
+  pub fn run_with<F>(&mut self, options:&RunOptions, mut handler:F) -> Result<()> where F:FnMut(&mut Zyre, Event) -> bool {
+    let mut handled:usize = 0;
+
+    loop {
+      let event = self.read_event()?;
+      let kind = event.event_type()?.to_string();
+
+      let started = Instant::now();
+      let outcome = catch_handler(|| handler(&mut *self, event));
+      let elapsed = started.elapsed();
+
+      if let Some(threshold) = options.slow_threshold {
+        if elapsed > threshold {
+          warn!("zyre handler took {:?} for a {} event, over the {:?} threshold", elapsed, kind, threshold);
+        }
+      }
+
+      let keep_going = match outcome {
+        Ok(keep_going) => keep_going,
+        Err(message) => {
+          self.record_panic("run handler", message);
+          if options.stop_on_panic {
+            return Err(Error::HandlerPanicked);
+          }
+          true
+        },
+      };
+      if !keep_going {
+        return Ok(());
+      }
+
+      handled += 1;
+      if options.yield_every.is_some_and(|every| every > 0 && handled.is_multiple_of(every)) {
+        options.on_yield.unwrap_or(thread::yield_now)();
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::Mutex;
+  use std::sync::atomic::{ AtomicUsize, Ordering };
+  use super::super::ZyreBuilder;
+  use super::super::tests::unique_port;
+
+  struct Capture(Mutex<Vec<String>>);
+
+  impl log::Log for Capture {
+    fn enabled(&self, metadata:&log::Metadata) -> bool {
+      metadata.level() <= log::Level::Warn
+    }
+
+    fn log(&self, record:&log::Record) {
+      self.0.lock().unwrap().push(record.args().to_string());
+    }
+
+    fn flush(&self) {}
+  }
+
+  static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+
+  #[test]
+  fn run_timed_warns() {
+    log::set_logger(&CAPTURE).ok();
+    log::set_max_level(log::LevelFilter::Warn);
+
+    let port = unique_port();
+    let mut zyre = ZyreBuilder::new().name("slow").port(port).build_started().unwrap();
+    let _peer = ZyreBuilder::new().name("peer").port(port).build_started().unwrap();
+
+    let mut handled = 0;
+    zyre.run_timed(Duration::from_millis(10), |_, _| {
+      thread::sleep(Duration::from_millis(50));
+      handled += 1;
+      false
+    }).unwrap();
+
+    assert_eq!(handled, 1);
+    assert!(CAPTURE.0.lock().unwrap().iter().any(|line| line.contains("over the 10ms threshold")));
+  }
+
+  #[test]
+  fn handler_panics() {
+    let port = unique_port();
+    let mut zyre = ZyreBuilder::new().name("survivor").port(port).build_started().unwrap();
+    // An ENTER, then a JOIN for each group.
+    let _peer = ZyreBuilder::new().name("peer").port(port).join("G1").join("G2").build_started().unwrap();
+
+    let mut handled = 0;
+    zyre.run(|_, _| {
+      handled += 1;
+      if handled == 1 {
+        panic!("boom on {}", handled);
+      }
+      handled < 2
+    }).unwrap();
+    assert_eq!(handled, 2);
+    assert_eq!(zyre.last_panic(), Some("boom on 1"));
+
+    let options = RunOptions { stop_on_panic: true, ..RunOptions::default() };
+    match zyre.run_with(&options, |_, _| panic!("stop here")) {
+      Err(Error::HandlerPanicked) => {},
+      other => panic!("expected HandlerPanicked, got {:?}", other),
+    }
+    assert_eq!(zyre.last_panic(), Some("stop here"));
+  }
+
+  static YIELDS: AtomicUsize = AtomicUsize::new(0);
+
+  fn count_yield() {
+    YIELDS.fetch_add(1, Ordering::SeqCst);
+  }
+
+  #[test]
+  fn yield_every() {
+    let port = unique_port();
+    let mut zyre = ZyreBuilder::new().name("yielding").port(port).build_started().unwrap();
+    // An ENTER and six JOINs.
+    let _peer = ZyreBuilder::new().name("peer").port(port)
+      .join("G1").join("G2").join("G3").join("G4").join("G5").join("G6")
+      .build_started().unwrap();
+
+    let options = RunOptions { yield_every: Some(3), on_yield: Some(count_yield), ..RunOptions::default() };
+    let mut handled = 0;
+    let mut yields_seen = Vec::new();
+    zyre.run_with(&options, |_, _| {
+      handled += 1;
+      yields_seen.push(YIELDS.load(Ordering::SeqCst));
+      handled < 7
+    }).unwrap();
+
+    assert_eq!(yields_seen, vec![0, 0, 0, 1, 1, 1, 2]);
+  }
+}
@@ -0,0 +1,226 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{ Context, Poll };
+use std::time::{ Duration, Instant };
+use futures_core::Stream;
+use super::{ correlation, unwrap_correlated, wrap_request, Error, Event, Message, OwnedEvent, Result, Zyre, REPLY_FRAME };
+
+/// Longest a single poll blocks the executor waiting on libzyre.
+const POLL_SLICE_MS: u64 = 10;
+
+/// A node's events as an async `Stream`.
+///
+/// libzyre offers nothing a waker can hook into, so each poll blocks for up
+/// to 10ms checking for an event, then asks to be polled again. That suits a
+/// few nodes per executor, not thousands.
+pub struct EventStream<'a> {
+  zyre: &'a mut Zyre,
+}
+
+impl<'a> Stream for EventStream<'a> {
+  type Item = Result<Event>;
+
+  fn poll_next(mut self:Pin<&mut Self>, cx:&mut Context<'_>) -> Poll<Option<Result<Event>>> {
+    match self.zyre.read_event_timeout(Duration::from_millis(POLL_SLICE_MS)) {
+      Ok(Some(event)) => Poll::Ready(Some(Ok(event))),
+      Ok(None) => {
+        cx.waker().wake_by_ref();
+        Poll::Pending
+      },
+      Err(err) => Poll::Ready(Some(Err(err))),
+    }
+  }
+}
+
+/// Like `EventStream`, but snapshots each event into an `OwnedEvent` so it can
+/// be held across `.await` points.
+pub struct OwnedEventStream<'a> {
+  zyre: &'a mut Zyre,
+}
+
+impl<'a> Stream for OwnedEventStream<'a> {
+  type Item = Result<OwnedEvent>;
+
+  fn poll_next(mut self:Pin<&mut Self>, cx:&mut Context<'_>) -> Poll<Option<Result<OwnedEvent>>> {
+    match self.zyre.read_event_timeout(Duration::from_millis(POLL_SLICE_MS)) {
+      Ok(Some(event)) => Poll::Ready(Some(event.snapshot())),
+      Ok(None) => {
+        cx.waker().wake_by_ref();
+        Poll::Pending
+      },
+      Err(err) => Poll::Ready(Some(Err(err))),
+    }
+  }
+}
+
+/// Resolves to the next event read straight from libzyre, or `None` once
+/// `deadline` passes.
+struct RecvEvent<'a> {
+  zyre: &'a mut Zyre,
+  deadline: Instant,
+}
+
+impl<'a> Future for RecvEvent<'a> {
+  type Output = Result<Option<Event>>;
+
+  fn poll(mut self:Pin<&mut Self>, cx:&mut Context<'_>) -> Poll<Result<Option<Event>>> {
+    let remaining = self.deadline.saturating_duration_since(Instant::now());
+    if remaining == Duration::from_millis(0) {
+      return Poll::Ready(Ok(None));
+    }
+
+    let slice = Duration::from_millis(POLL_SLICE_MS).min(remaining);
+    match self.zyre.recv_event_timeout(slice) {
+      Ok(None) => {
+        cx.waker().wake_by_ref();
+        Poll::Pending
+      },
+      other => Poll::Ready(other),
+    }
+  }
+}
+
+impl Zyre {
+  pub fn events(&mut self) -> EventStream<'_> {
+    EventStream { zyre: self }
+  }
+
+  pub fn owned_events(&mut self) -> OwnedEventStream<'_> {
+    OwnedEventStream { zyre: self }
+  }
+
+  /// Whispers `msg` to `peer` and waits for the reply it sends with
+  /// `Zyre::reply_to`. Unrelated events read while waiting are kept and
+  /// returned by later reads.
+  pub async fn request<T>(&mut self, peer:T, mut msg:Message, timeout:Duration) -> Result<Message>
+  where T:AsRef<str> {
+    let peer = peer.as_ref().to_string();
+    let id = wrap_request(&mut msg)?;
+    self.whisper(&peer, msg)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut skipped = Vec::new();
+    let result = loop {
+      match (RecvEvent { zyre: self, deadline }).await {
+        Ok(Some(mut event)) => {
+          if is_reply(&event, &peer, &id) {
+            break Ok(unwrap_correlated(&mut event));
+          }
+          skipped.push(event);
+        },
+        Ok(None) => break Err(Error::Timeout),
+        Err(err) => break Err(err),
+      }
+    };
+    self.pending.extend(skipped);
+
+    result
+  }
+}
+
+fn is_reply(event:&Event, peer:&str, id:&str) -> bool {
+  event.event_type().ok() == Some("WHISPER")
+    && event.peer_uuid().ok() == Some(peer)
+    && correlation(event, REPLY_FRAME).as_deref() == Some(id)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+  use super::*;
+  use std::sync::Arc;
+  use std::sync::mpsc;
+  use std::task::{ Wake, Waker };
+  use std::thread;
+  use super::super::{ EventType, ZyreBuilder };
+  use super::super::tests::{ read_until, unique_port };
+
+  struct ThreadWaker(thread::Thread);
+
+  impl Wake for ThreadWaker {
+    fn wake(self:Arc<Self>) {
+      self.0.unpark();
+    }
+  }
+
+  /// Just enough of an executor to drive one future on the test thread.
+  pub fn block_on<F:Future>(future:F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+      match future.as_mut().poll(&mut cx) {
+        Poll::Ready(output) => return output,
+        Poll::Pending => thread::park(),
+      }
+    }
+  }
+
+  /// Resolves to the stream's next item.
+  struct Next<'s, S:Stream + Unpin>(&'s mut S);
+
+  impl<'s, S:Stream + Unpin> Future for Next<'s, S> {
+    type Output = Option<S::Item>;
+
+    fn poll(mut self:Pin<&mut Self>, cx:&mut Context<'_>) -> Poll<Option<S::Item>> {
+      Pin::new(&mut *self.0).poll_next(cx)
+    }
+  }
+
+  #[test]
+  fn owned_events() {
+    let port = unique_port();
+    let first = ZyreBuilder::new().name("first").port(port).join("owned").build_started().unwrap();
+    let mut second = ZyreBuilder::new().name("second").port(port).join("owned").build_started().unwrap();
+    let first_uuid = first.uuid().unwrap().to_string();
+
+    let (enter, join) = block_on(async {
+      let mut events = second.owned_events();
+      let mut enter = None;
+      loop {
+        let event = Next(&mut events).await.unwrap().unwrap();
+        match event.kind() {
+          EventType::Enter if enter.is_none() => enter = Some(event),
+          EventType::Join => return (enter.unwrap(), event),
+          _ => {},
+        }
+      }
+    });
+
+    assert_eq!(enter.peer_uuid, first_uuid);
+    assert_eq!(enter.peer_name, "first");
+    assert_eq!(join.group.as_deref(), Some("owned"));
+  }
+
+  #[test]
+  fn request_reply() {
+    let port = unique_port();
+    let (uuid_tx, uuid_rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+
+    let responder = thread::spawn(move || {
+      let mut zyre = ZyreBuilder::new().name("responder").port(port).build().unwrap();
+      zyre.start().unwrap();
+      uuid_tx.send(zyre.uuid().unwrap().to_string()).unwrap();
+
+      let request = read_until(&mut zyre, |event| event.event_type().unwrap() == "WHISPER");
+      assert_eq!(request.message_ref().unwrap().frames().last(), Some(&&b"ping"[..]));
+      zyre.reply_to(&request, Message::from_frames(vec!["pong"]).unwrap()).unwrap();
+      done_rx.recv().ok();
+    });
+
+    let mut zyre = ZyreBuilder::new().name("requester").port(port).build().unwrap();
+    zyre.start().unwrap();
+    let responder_uuid = uuid_rx.recv().unwrap();
+    read_until(&mut zyre, |event| {
+      event.event_type().unwrap() == "ENTER" && event.peer_uuid().unwrap() == responder_uuid
+    });
+
+    let request = Message::from_frames(vec!["ping"]).unwrap();
+    let mut reply = block_on(zyre.request(&responder_uuid, request, Duration::from_secs(5))).unwrap();
+    assert_eq!(reply.collect().unwrap(), vec!["pong"]);
+
+    done_tx.send(()).unwrap();
+    responder.join().unwrap();
+  }
+}
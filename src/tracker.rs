@@ -0,0 +1,281 @@
+use std::collections::{ HashMap, HashSet };
+use std::time::{ Duration, Instant };
+use super::{ EventType, OwnedEvent };
+
+/// Flags peers that have been quiet for longer than a threshold, usually well
+/// before libzyre gives up on them with EXIT.
+///
+/// libzyre's own keepalives never surface as events, so this only works if
+/// peers send something regularly, such as `Zyre::shout_stats`.
+pub struct PartitionDetector {
+  threshold: Duration,
+  last_seen: HashMap<String, Instant>,
+}
+
+impl PartitionDetector {
+  pub fn new(threshold:Duration) -> PartitionDetector {
+    PartitionDetector {
+      threshold,
+      last_seen: HashMap::new(),
+    }
+  }
+
+  pub fn observe(&mut self, event:&OwnedEvent) {
+    match event.event_type.as_str() {
+      "EXIT" => {
+        self.last_seen.remove(&event.peer_uuid);
+      },
+      // These report that the peer has gone quiet, not that we heard from it.
+      "EVASIVE" | "SILENT" => {},
+      _ => {
+        self.last_seen.insert(event.peer_uuid.clone(), Instant::now());
+      },
+    }
+  }
+
+  /// UUIDs of peers not heard from within the threshold, sorted.
+  pub fn partitioned(&self) -> Vec<String> {
+    let mut peers:Vec<String> = self.last_seen.iter()
+      .filter(|&(_, seen)| seen.elapsed() > self.threshold)
+      .map(|(uuid, _)| uuid.clone())
+      .collect();
+    peers.sort();
+    peers
+  }
+}
+
+/// A live model of which peers are in which groups, built from the JOIN,
+/// LEAVE and EXIT events fed to it, rather than point-in-time queries such
+/// as `Zyre::peers_by_group`.
+#[derive(Clone, Debug, Default)]
+pub struct GroupTracker {
+  groups: HashMap<String, HashSet<String>>,
+}
+
+impl GroupTracker {
+  pub fn new() -> GroupTracker {
+    GroupTracker::default()
+  }
+
+  pub fn observe(&mut self, event:&OwnedEvent) {
+    match (event.kind(), &event.group) {
+      (EventType::Join, Some(group)) => {
+        self.groups.entry(group.clone()).or_default().insert(event.peer_uuid.clone());
+      },
+      (EventType::Leave, Some(group)) => {
+        let empty = match self.groups.get_mut(group) {
+          Some(members) => {
+            members.remove(&event.peer_uuid);
+            members.is_empty()
+          },
+          None => false,
+        };
+        if empty {
+          self.groups.remove(group);
+        }
+      },
+      (EventType::Exit, _) => {
+        for members in self.groups.values_mut() {
+          members.remove(&event.peer_uuid);
+        }
+        self.groups.retain(|_, members| !members.is_empty());
+      },
+      _ => {},
+    }
+  }
+
+  /// UUIDs of the peers in `group`, sorted.
+  pub fn members(&self, group:&str) -> Vec<String> {
+    let mut members: Vec<String> = self.groups.get(group).into_iter().flatten().cloned().collect();
+    members.sort();
+    members
+  }
+
+  /// Groups with at least one peer in them, sorted.
+  pub fn groups(&self) -> Vec<String> {
+    let mut groups: Vec<String> = self.groups.keys().cloned().collect();
+    groups.sort();
+    groups
+  }
+
+  pub fn state(&self) -> &HashMap<String, HashSet<String>> {
+    &self.groups
+  }
+}
+
+/// A roster of peers that rides out flapping: a peer that EXITs stays listed
+/// until it has been gone for the debounce window, and an ENTER within the
+/// window cancels the departure, so UIs don't flicker on flaky networks.
+#[derive(Clone, Debug)]
+pub struct DebouncedMembership {
+  window: Duration,
+  present: HashSet<String>,
+  leaving: HashMap<String, Instant>,
+}
+
+impl DebouncedMembership {
+  pub fn new(window:Duration) -> DebouncedMembership {
+    DebouncedMembership {
+      window,
+      present: HashSet::new(),
+      leaving: HashMap::new(),
+    }
+  }
+
+  pub fn observe(&mut self, event:&OwnedEvent) {
+    match event.kind() {
+      EventType::Enter => {
+        self.leaving.remove(&event.peer_uuid);
+        self.present.insert(event.peer_uuid.clone());
+      },
+      EventType::Exit if self.present.contains(&event.peer_uuid) => {
+        self.leaving.entry(event.peer_uuid.clone()).or_insert_with(Instant::now);
+      },
+      _ => {},
+    }
+  }
+
+  /// UUIDs of peers present or gone for less than the window, sorted.
+  pub fn peers(&self) -> Vec<String> {
+    let mut peers: Vec<String> = self.present.iter()
+      .filter(|uuid| !self.departed(uuid))
+      .cloned()
+      .collect();
+    peers.sort();
+    peers
+  }
+
+  /// Removes and returns, sorted, the peers that have now been gone for the
+  /// whole window. Each departure is reported once.
+  pub fn gone(&mut self) -> Vec<String> {
+    let mut gone: Vec<String> = self.leaving.keys()
+      .filter(|uuid| self.departed(uuid))
+      .cloned()
+      .collect();
+    gone.sort();
+
+    for uuid in &gone {
+      self.leaving.remove(uuid);
+      self.present.remove(uuid);
+    }
+    gone
+  }
+
+  fn departed(&self, uuid:&str) -> bool {
+    self.leaving.get(uuid).is_some_and(|since| since.elapsed() >= self.window)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::thread;
+  use super::super::{ Message, ZyreBuilder };
+  use super::super::tests::{ read_until, unique_port };
+
+  fn event(event_type:&str, peer_uuid:&str) -> OwnedEvent {
+    OwnedEvent {
+      event_type: event_type.to_string(),
+      peer_uuid: peer_uuid.to_string(),
+      peer_name: String::new(),
+      peer_addr: None,
+      headers: HashMap::new(),
+      group: None,
+      frames: Vec::new(),
+    }
+  }
+
+  fn group_event(event_type:&str, peer_uuid:&str, group:&str) -> OwnedEvent {
+    OwnedEvent {
+      group: Some(group.to_string()),
+      ..event(event_type, peer_uuid)
+    }
+  }
+
+  #[test]
+  fn group_tracker() {
+    let mut tracker = GroupTracker::new();
+    tracker.observe(&group_event("JOIN", "a", "RED"));
+    tracker.observe(&group_event("JOIN", "b", "RED"));
+    tracker.observe(&group_event("JOIN", "a", "BLUE"));
+    tracker.observe(&group_event("JOIN", "c", "GREEN"));
+    assert_eq!(tracker.members("RED"), vec!["a", "b"]);
+    assert_eq!(tracker.groups(), vec!["BLUE", "GREEN", "RED"]);
+
+    tracker.observe(&group_event("LEAVE", "b", "RED"));
+    tracker.observe(&group_event("LEAVE", "c", "GREEN"));
+    tracker.observe(&event("EXIT", "a"));
+
+    assert!(tracker.members("RED").is_empty());
+    assert!(tracker.groups().is_empty());
+    assert!(tracker.state().is_empty());
+  }
+
+  #[test]
+  fn debounced_flap() {
+    let mut roster = DebouncedMembership::new(Duration::from_millis(50));
+    roster.observe(&event("ENTER", "a"));
+    roster.observe(&event("ENTER", "b"));
+
+    roster.observe(&event("EXIT", "a"));
+    assert_eq!(roster.peers(), vec!["a", "b"]);
+    roster.observe(&event("ENTER", "a"));
+    roster.observe(&event("EXIT", "b"));
+    thread::sleep(Duration::from_millis(60));
+
+    assert_eq!(roster.peers(), vec!["a"]);
+    assert_eq!(roster.gone(), vec!["b"]);
+    assert!(roster.gone().is_empty());
+    assert_eq!(roster.peers(), vec!["a"]);
+  }
+
+  #[test]
+  fn exit_forgets_peer() {
+    let mut detector = PartitionDetector::new(Duration::from_millis(0));
+    detector.observe(&event("ENTER", "a"));
+    detector.observe(&event("ENTER", "b"));
+    detector.observe(&event("EXIT", "a"));
+    thread::sleep(Duration::from_millis(5));
+
+    assert_eq!(detector.partitioned(), vec!["b"]);
+  }
+
+  #[test]
+  fn stalled_peer_partitioned() {
+    let port = unique_port();
+    let mut observer = ZyreBuilder::new().name("observer").port(port).join("PULSE").build_started().unwrap();
+    let mut live = ZyreBuilder::new().name("live").port(port).join("PULSE").build_started().unwrap();
+    let mut stalled = ZyreBuilder::new().name("stalled").port(port).join("PULSE").build_started().unwrap();
+    let stalled_uuid = stalled.uuid().unwrap().to_string();
+    let mut detector = PartitionDetector::new(Duration::from_millis(300));
+
+    // Both peers talk once, so the detector knows them.
+    read_until(&mut live, |event| event.kind().unwrap() == EventType::Join && event.peer_name().unwrap() == "observer");
+    read_until(&mut stalled, |event| event.kind().unwrap() == EventType::Join && event.peer_name().unwrap() == "observer");
+    live.shout("PULSE", Message::from_frames(vec!["beat"]).unwrap()).unwrap();
+    stalled.shout("PULSE", Message::from_frames(vec!["beat"]).unwrap()).unwrap();
+    let mut heard = HashSet::new();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while heard.len() < 2 {
+      assert!(Instant::now() < deadline, "never heard from both peers");
+      if let Some(event) = observer.read_event_timeout(Duration::from_millis(10)).unwrap() {
+        if event.kind().unwrap() == EventType::Shout {
+          heard.insert(event.peer_uuid().unwrap().to_string());
+        }
+        detector.observe(&event.snapshot().unwrap());
+      }
+    }
+
+    // The stalled peer stays connected but goes quiet while the live one
+    // keeps shouting.
+    let until = Instant::now() + Duration::from_millis(600);
+    while Instant::now() < until {
+      live.shout("PULSE", Message::from_frames(vec!["beat"]).unwrap()).unwrap();
+      while let Some(event) = observer.read_event_timeout(Duration::from_millis(50)).unwrap() {
+        detector.observe(&event.snapshot().unwrap());
+      }
+    }
+
+    assert_eq!(detector.partitioned(), vec![stalled_uuid]);
+  }
+}
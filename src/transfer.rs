@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::fs::{ self, File };
+use std::io::{ Read, Write };
+use std::path::{ Path, PathBuf };
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use super::{ timestamp, whisper_frames, Error, Event, EventType, Message, Result, Zyre };
+
+/// First frame of the whisper announcing a file.
+const FILE_FRAME: &str = "$ZYRE-FILE";
+/// First frame of each whisper carrying part of a file.
+const CHUNK_FRAME: &str = "$ZYRE-CHUNK";
+
+/// Tells apart transfers started within the same microsecond.
+static NEXT_TRANSFER: AtomicUsize = AtomicUsize::new(0);
+
+impl Zyre {
+  /// Whispers the file at `path` to `peer` in pieces of at most `chunk`
+  /// bytes, reading it as it goes so it never has to fit in memory. The
+  /// peer reassembles it with a `FileReceiver`.
+  ///
+  /// The first whisper is `[$ZYRE-FILE, id, file name, total size]`, and each
+  /// following one `[$ZYRE-CHUNK, id, bytes]`, where `id` tells concurrent
+  /// transfers apart and the size is in decimal. libzyre delivers whispers
+  /// to a peer in order, so no offsets are needed.
+  pub fn whisper_file(&mut self, peer:&str, path:&str, chunk:usize) -> Result<()> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let name = Path::new(path).file_name().and_then(|name| name.to_str()).ok_or(Error::InvalidFrame)?;
+    let id = format!("{}-{}", timestamp(), NEXT_TRANSFER.fetch_add(1, Ordering::SeqCst));
+
+    let mut header = Message::new();
+    header.push(size.to_string())?;
+    header.push(name)?;
+    header.push(&id)?;
+    header.push(FILE_FRAME)?;
+    self.whisper(peer, header)?;
+
+    let mut buf = vec![0; chunk.max(1)];
+    loop {
+      let read = file.read(&mut buf)?;
+      if read == 0 {
+        break;
+      }
+
+      let mut msg = Message::new();
+      msg.push_bytes(&buf[..read]);
+      msg.push(&id)?;
+      msg.push(CHUNK_FRAME)?;
+      self.whisper(peer, msg)?;
+    }
+
+    Ok(())
+  }
+}
+
+/// Writes files sent with `Zyre::whisper_file` into a directory, chunk by
+/// chunk as they arrive.
+pub struct FileReceiver {
+  dir: PathBuf,
+  transfers: HashMap<(String, String), Transfer>,
+}
+
+struct Transfer {
+  path: PathBuf,
+  file: File,
+  remaining: u64,
+}
+
+impl FileReceiver {
+  pub fn new<P>(dir:P) -> FileReceiver where P:AsRef<Path> {
+    FileReceiver {
+      dir: dir.as_ref().to_path_buf(),
+      transfers: HashMap::new(),
+    }
+  }
+
+  /// Handles a file-transfer whisper, returning where the file was written
+  /// once its last chunk arrives. When a peer EXITs, its unfinished
+  /// transfers are closed and their partial files deleted, with a warning
+  /// logged for each. Any other event is ignored.
+  ///
+  /// Only the final component of the sender's file name is used, so a peer
+  /// can't write outside the directory. Fails with `Error::InvalidFrame` for
+  /// a malformed whisper, a chunk of an unknown transfer, or more bytes than
+  /// announced; a transfer that fails is discarded along with its partial
+  /// file.
+  pub fn handle(&mut self, event:&Event) -> Result<Option<PathBuf>> {
+    let peer = match event.peer_uuid() {
+      Ok(peer) => peer.to_string(),
+      Err(_) => return Ok(None),
+    };
+
+    if let Ok(EventType::Exit) = event.kind() {
+      self.abandon(&peer);
+      return Ok(None);
+    }
+
+    if let Some(frames) = whisper_frames(event, FILE_FRAME) {
+      let (id, name, size) = match frames.as_slice() {
+        [_, id, name, size] => (text(id)?, text(name)?, text(size)?),
+        _ => return Err(Error::InvalidFrame),
+      };
+      let name = Path::new(name).file_name().ok_or(Error::InvalidFrame)?;
+      let remaining = size.parse().map_err(|_| Error::InvalidFrame)?;
+
+      let path = self.dir.join(name);
+      let transfer = Transfer { file: File::create(&path)?, path, remaining };
+      return self.finish_if_done((peer, id.to_string()), transfer);
+    }
+
+    if let Some(frames) = whisper_frames(event, CHUNK_FRAME) {
+      let (id, bytes) = match frames.as_slice() {
+        [_, id, bytes] => (text(id)?, bytes),
+        _ => return Err(Error::InvalidFrame),
+      };
+      let key = (peer, id.to_string());
+      let mut transfer = self.transfers.remove(&key).ok_or(Error::InvalidFrame)?;
+
+      if bytes.len() as u64 > transfer.remaining {
+        discard(transfer);
+        return Err(Error::InvalidFrame);
+      }
+      if let Err(err) = transfer.file.write_all(bytes) {
+        discard(transfer);
+        return Err(err.into());
+      }
+      transfer.remaining -= bytes.len() as u64;
+
+      return self.finish_if_done(key, transfer);
+    }
+
+    Ok(None)
+  }
+
+  /// Discards every unfinished transfer from `peer`.
+  fn abandon(&mut self, peer:&str) {
+    let keys:Vec<(String, String)> = self.transfers.keys().filter(|(from, _)| from == peer).cloned().collect();
+
+    for key in keys {
+      if let Some(transfer) = self.transfers.remove(&key) {
+        log::warn!("zyre peer {} left with {} bytes of {} unsent", peer, transfer.remaining, transfer.path.display());
+        discard(transfer);
+      }
+    }
+  }
+
+  fn finish_if_done(&mut self, key:(String, String), mut transfer:Transfer) -> Result<Option<PathBuf>> {
+    if transfer.remaining > 0 {
+      self.transfers.insert(key, transfer);
+      return Ok(None);
+    }
+
+    transfer.file.flush()?;
+    Ok(Some(transfer.path))
+  }
+}
+
+/// Closes an unfinished transfer and deletes what was written of it.
+fn discard(transfer:Transfer) {
+  drop(transfer.file);
+  fs::remove_file(&transfer.path).ok();
+}
+
+fn text(frame:&[u8]) -> Result<&str> {
+  Ok(std::str::from_utf8(frame)?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::{ Duration, Instant };
+  use super::super::{ EventType, ZyreBuilder };
+  use super::super::tests::{ read_until, unique_port };
+
+  #[test]
+  fn file_round_trip() {
+    let port = unique_port();
+    let root = std::env::temp_dir().join(format!("zyre-transfer-{}-{}", std::process::id(), port));
+    let (outbox, inbox) = (root.join("out"), root.join("in"));
+    fs::create_dir_all(&outbox).unwrap();
+    fs::create_dir_all(&inbox).unwrap();
+
+    let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+    let source = outbox.join("payload.bin");
+    fs::write(&source, &data).unwrap();
+
+    let mut sender = ZyreBuilder::new().name("sender").port(port).build_started().unwrap();
+    let mut receiver = ZyreBuilder::new().name("receiver").port(port).build_started().unwrap();
+    let receiver_uuid = receiver.uuid().unwrap().to_string();
+    read_until(&mut sender, |event| event.kind().unwrap() == EventType::Enter);
+
+    sender.whisper_file(&receiver_uuid, source.to_str().unwrap(), 1024).unwrap();
+
+    let mut files = FileReceiver::new(&inbox);
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let path = loop {
+      assert!(Instant::now() < deadline, "transfer never finished");
+      if let Some(event) = receiver.read_event_timeout(Duration::from_millis(100)).unwrap() {
+        if let Some(path) = files.handle(&event).unwrap() {
+          break path;
+        }
+      }
+    };
+
+    assert_eq!(path, inbox.join("payload.bin"));
+    assert_eq!(fs::read(&path).unwrap(), data);
+    fs::remove_dir_all(&root).ok();
+  }
+
+  #[test]
+  fn partial_file_removed_on_exit() {
+    let port = unique_port();
+    let inbox = std::env::temp_dir().join(format!("zyre-partial-{}-{}", std::process::id(), port));
+    fs::create_dir_all(&inbox).unwrap();
+
+    let mut sender = ZyreBuilder::new().name("sender").port(port).build_started().unwrap();
+    let mut receiver = ZyreBuilder::new().name("receiver").port(port).build_started().unwrap();
+    let receiver_uuid = receiver.uuid().unwrap().to_string();
+    read_until(&mut sender, |event| event.kind().unwrap() == EventType::Enter);
+
+    // Announce more than is ever sent.
+    let mut header = Message::new();
+    header.push("10000").unwrap();
+    header.push("partial.bin").unwrap();
+    header.push("1").unwrap();
+    header.push(FILE_FRAME).unwrap();
+    sender.whisper(&receiver_uuid, header).unwrap();
+
+    let mut files = FileReceiver::new(&inbox);
+    let announced = read_until(&mut receiver, |event| event.kind().unwrap() == EventType::Whisper);
+    files.handle(&announced).unwrap();
+    assert!(inbox.join("partial.bin").exists());
+
+    drop(sender);
+    let exit = read_until(&mut receiver, |event| event.kind().unwrap() == EventType::Exit);
+    files.handle(&exit).unwrap();
+
+    assert!(files.transfers.is_empty());
+    assert!(!inbox.join("partial.bin").exists());
+    fs::remove_dir_all(&inbox).ok();
+  }
+}
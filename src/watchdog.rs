@@ -0,0 +1,142 @@
+use std::thread;
+use std::time::Duration;
+use super::{ Result, Zyre };
+
+/// How long a `Watchdog` waits between attempts to restart a node.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReconnectPolicy {
+  /// Give up after the first failure.
+  Never,
+  /// Wait the same time after every failure.
+  Fixed(Duration),
+  /// Wait `base`, doubling after each further failure, up to `max`.
+  ExponentialBackoff { base: Duration, max: Duration },
+}
+
+/// Counts consecutive restart failures and turns them into delays according
+/// to a `ReconnectPolicy`.
+#[derive(Clone, Debug)]
+pub struct Watchdog {
+  policy: ReconnectPolicy,
+  failures: u32,
+}
+
+impl Watchdog {
+  pub fn new(policy:ReconnectPolicy) -> Watchdog {
+    Watchdog {
+      policy,
+      failures: 0,
+    }
+  }
+
+  /// Records a failure and returns how long to wait before trying again, or
+  /// `None` if the policy gives up.
+  pub fn next_delay(&mut self) -> Option<Duration> {
+    self.failures = self.failures.saturating_add(1);
+
+    match self.policy {
+      ReconnectPolicy::Never => None,
+      ReconnectPolicy::Fixed(delay) => Some(delay),
+      ReconnectPolicy::ExponentialBackoff { base, max } => {
+        let delay = 1u32.checked_shl(self.failures - 1).and_then(|factor| base.checked_mul(factor));
+        Some(delay.map_or(max, |delay| delay.min(max)))
+      },
+    }
+  }
+
+  /// Consecutive failures since the last success.
+  pub fn failures(&self) -> u32 {
+    self.failures
+  }
+
+  /// Records a success, so the next failure starts the policy over.
+  pub fn reset(&mut self) {
+    self.failures = 0;
+  }
+}
+
+impl Zyre {
+  /// Calls `restart` until it succeeds, sleeping between attempts as the
+  /// watchdog's policy says. Returns the last error once the policy gives
+  /// up.
+  pub fn restart_with(&mut self, watchdog:&mut Watchdog) -> Result<()> {
+    loop {
+      match self.restart() {
+        Ok(()) => {
+          watchdog.reset();
+          return Ok(());
+        },
+        Err(err) => match watchdog.next_delay() {
+          Some(delay) => thread::sleep(delay),
+          None => return Err(err),
+        },
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::ZyreBuilder;
+  use super::super::tests::unique_port;
+
+  fn ms(millis:u64) -> Duration {
+    Duration::from_millis(millis)
+  }
+
+  #[test]
+  fn exponential_backoff_caps() {
+    let mut watchdog = Watchdog::new(ReconnectPolicy::ExponentialBackoff { base: ms(100), max: ms(1000) });
+
+    let delays: Vec<_> = (0..6).map(|_| watchdog.next_delay().unwrap()).collect();
+    assert_eq!(delays, vec![ms(100), ms(200), ms(400), ms(800), ms(1000), ms(1000)]);
+    assert_eq!(watchdog.failures(), 6);
+
+    for _ in 0..100 {
+      assert_eq!(watchdog.next_delay(), Some(ms(1000)));
+    }
+
+    watchdog.reset();
+    assert_eq!(watchdog.next_delay(), Some(ms(100)));
+  }
+
+  #[test]
+  fn fixed_and_never() {
+    let mut fixed = Watchdog::new(ReconnectPolicy::Fixed(ms(250)));
+    assert_eq!(fixed.next_delay(), Some(ms(250)));
+    assert_eq!(fixed.next_delay(), Some(ms(250)));
+
+    let mut never = Watchdog::new(ReconnectPolicy::Never);
+    assert_eq!(never.next_delay(), None);
+  }
+
+  #[test]
+  fn restart_with() {
+    let mut zyre = Zyre::new(Some("watched")).unwrap();
+    zyre.start().unwrap();
+
+    let mut watchdog = Watchdog::new(ReconnectPolicy::Never);
+    zyre.restart_with(&mut watchdog).unwrap();
+    assert!(zyre.is_started());
+    assert_eq!(watchdog.failures(), 0);
+  }
+
+  #[test]
+  fn restart_with_after_failure() {
+    let mut zyre = ZyreBuilder::new().name("watched").port(unique_port()).join("WATCHED").build_started().unwrap();
+    let original = zyre.uuid().unwrap().to_string();
+
+    // libzyre won't take this name, so the node is destroyed and not replaced.
+    zyre.config.name = Some("watched\0".to_string());
+    assert!(zyre.restart().is_err());
+    assert!(!zyre.is_started());
+
+    zyre.config.name = Some("watched".to_string());
+    let mut watchdog = Watchdog::new(ReconnectPolicy::Never);
+    zyre.restart_with(&mut watchdog).unwrap();
+    assert!(zyre.is_started());
+    assert_eq!(zyre.previous_uuid(), Some(original.as_str()));
+    assert_eq!(zyre.own_groups().unwrap(), vec!["WATCHED".to_string()]);
+  }
+}
@@ -0,0 +1,35 @@
+//! Tests that change czmq's process-wide settings. They live in their own
+//! binary so the unit tests never see the change, and hold `GLOBAL` so they
+//! don't see each other's.
+
+use std::sync::{ Mutex, MutexGuard };
+use zyre::ZyreBuilder;
+
+static GLOBAL: Mutex<()> = Mutex::new(());
+
+fn global() -> MutexGuard<'static, ()> {
+  GLOBAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[test]
+fn multicast_address_starts() {
+  let _global = global();
+  let result = ZyreBuilder::new().name("multicast").port(5700).multicast_address("239.192.0.1").build_started();
+
+  // Put the default back before asserting, so a failure can't leak it.
+  unsafe {
+    zyre_sys::zsys_set_ipv4_mcast_address(std::ptr::null());
+  }
+  assert!(result.unwrap().is_started());
+}
+
+#[test]
+fn interrupted_flag() {
+  let _global = global();
+  zyre::set_interrupted(true);
+  let observed = zyre::interrupted();
+  zyre::set_interrupted(false);
+
+  assert!(observed);
+  assert!(!zyre::interrupted());
+}